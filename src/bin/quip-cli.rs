@@ -0,0 +1,425 @@
+use clap::Parser;
+use native_tls::TlsConnector;
+use quip::{
+    QuipError, QuipResult,
+    client::{Client, PushStream},
+    request::RequestBody,
+    response::{
+        BlockPolicy, Capability, GroupRole, HistoryEntry, Presence, Response, ResponseBody,
+        UserInfo,
+    },
+};
+use rustyline::{DefaultEditor, ExternalPrinter, error::ReadlineError};
+use tokio::sync::mpsc;
+
+const HELP: &str = "\
+Commands:
+  /login <NAME> <PASSWORD>          Login with user name and password
+  /resume <TOKEN>                   Resume a dropped session with its token
+  /msg <USER|G:GROUP> <TEXT>        Send message to a user or a group
+  /live <USER|G:GROUP> <TEXT>       Send message only to online users
+  /group create <GROUP>             Create a group
+  /group add <GROUP> <USER>         Add a user to a group
+  /group remove <GROUP> <USER>      Remove a user from a group
+  /group destroy <GROUP>            Destroy a group
+  /group role <GROUP> <USER> <ROLE> Set role to owner, moderator or member
+  /who [GROUP]                      List online users, optionally in a group
+  /whois <USER>                     Show information of a user
+  /away                             Set presence to away
+  /back                             Set presence to online
+  /subscribe <USER>                 Receive presence of a user
+  /unsubscribe <USER>               Stop receiving presence of a user
+  /history <USER|G:GROUP> [BEFORE]  Show messages older than an ID
+  /block <USER> [reject]            Block messages from a user, silently by default
+  /unblock <USER>                   Unblock messages from a user
+  /blocks                           List blocked users
+  /kick <USER> [REASON]             Close sessions of a user, admin only
+  /ban <USER> [SECONDS]             Ban a user, forever by default, admin only
+  /unban <USER>                     Lift ban of a user, admin only
+  /mute <USER> [SECONDS]            Mute a user, forever by default, admin only
+  /unmute <USER>                    Lift mute of a user, admin only
+  /announce <TEXT>                  Send a notice to all online users, admin only
+  /help                             Show this message
+  /quit                             Logout and exit";
+
+/// Interactive Quip client.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    /// Server address, or the address server reads from with two-socket
+    /// SSL/TLS.
+    #[arg(default_value = "127.0.0.1:1145")]
+    addr: String,
+
+    /// Connect with SSL/TLS, the optional value is the address server writes
+    /// to, which enables the two-socket mode.
+    #[arg(long, value_name = "WRITE_ADDR", num_args = 0..=1)]
+    tls: Option<Option<String>>,
+
+    /// Domain name for verifying server certificate.
+    #[arg(long, default_value = "localhost")]
+    domain: String,
+
+    /// Accept invalid server certificates, only for testing.
+    #[arg(long)]
+    insecure: bool,
+}
+
+#[tokio::main]
+async fn main() -> QuipResult<()> {
+    env_logger::init();
+
+    let args = Args::parse();
+
+    let (client, pushes) = match &args.tls {
+        Some(write_addr) => {
+            let connector = TlsConnector::builder()
+                .danger_accept_invalid_certs(args.insecure)
+                .build()?;
+            match write_addr {
+                Some(write_addr) => {
+                    Client::connect_tls(&args.addr, write_addr, &args.domain, connector).await?
+                }
+                None => Client::connect_tls_duplex(&args.addr, &args.domain, connector).await?,
+            }
+        }
+        None => Client::connect_tcp(&args.addr).await?,
+    };
+    client.cap(&Capability::ALL).await?;
+
+    let mut editor = DefaultEditor::new().map_err(readline_error)?;
+    let printer: Box<dyn ExternalPrinter + Send> = match editor.create_external_printer() {
+        Ok(printer) => Box::new(printer),
+        Err(_) => Box::new(StdoutPrinter),
+    };
+
+    // Editor blocks the thread, so lines are sent to the async loop.
+    let (line_tx, line_rx) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        loop {
+            let line = match editor.readline("> ") {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => {
+                    let _ = line_tx.send("/quit".into());
+                    break;
+                }
+                Err(_) => break,
+            };
+
+            if !line.trim().is_empty() {
+                let _ = editor.add_history_entry(line.as_str());
+            }
+
+            if line_tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    run(client, pushes, line_rx, printer).await
+}
+
+/// Printer for output which is not a terminal.
+struct StdoutPrinter;
+
+impl ExternalPrinter for StdoutPrinter {
+    fn print(&mut self, msg: String) -> rustyline::Result<()> {
+        println!("{}", msg);
+        Ok(())
+    }
+}
+
+async fn run(
+    client: Client,
+    mut pushes: PushStream,
+    mut lines: mpsc::UnboundedReceiver<String>,
+    mut printer: Box<dyn ExternalPrinter + Send>,
+) -> QuipResult<()> {
+    let mut print = |msg: String| {
+        let _ = printer.print(msg);
+    };
+
+    print("Connected, type /help for commands".into());
+
+    loop {
+        tokio::select! {
+            resp = pushes.recv() => match resp {
+                Some(Response { body: ResponseBody::Ping(token), .. }) => {
+                    if let Err(err) = client.pong(token).await {
+                        print(format!("Error: {}", err));
+                    }
+                }
+                Some(resp) => {
+                    let id = resp.message_id();
+                    print(format_push(resp));
+
+                    if let Some(id) = id
+                        && let Err(err) = client.ack(id).await
+                    {
+                        print(format!("Error: {}", err));
+                    }
+                }
+                None => {
+                    print("Disconnected by server".into());
+                    return Ok(());
+                }
+            },
+            line = lines.recv() => {
+                let line = match line {
+                    Some(line) => line,
+                    None => return Ok(()),
+                };
+
+                match serve_line(&client, line.trim()).await {
+                    Ok(Some(msg)) => print(msg),
+                    Ok(None) => {}
+                    Err(QuipError::Disconnect) => return Ok(()),
+                    Err(err) => print(format!("Error: {}", err)),
+                }
+            }
+        }
+    }
+}
+
+/// Process an input line, returns message to print, or
+/// [`QuipError::Disconnect`] if the client should exit.
+async fn serve_line(client: &Client, line: &str) -> QuipResult<Option<String>> {
+    if line.is_empty() {
+        return Ok(None);
+    }
+
+    let (cmd, rest) = split_word(line);
+    let body = match cmd {
+        "/help" => return Ok(Some(HELP.into())),
+        "/quit" => {
+            client.logout().await?;
+            return Err(QuipError::Disconnect);
+        }
+        "/login" => match split_args::<2>(rest) {
+            Some([name, password]) => RequestBody::Login(name, password),
+            None => return Ok(Some("Usage: /login <NAME> <PASSWORD>".into())),
+        },
+        "/resume" => match split_args::<1>(rest) {
+            Some([token]) => RequestBody::Resume(token),
+            None => return Ok(Some("Usage: /resume <TOKEN>".into())),
+        },
+        "/msg" => match split_word(rest) {
+            (receiver, msg) if !receiver.is_empty() && !msg.is_empty() => {
+                RequestBody::Send(receiver.into(), msg.into(), false)
+            }
+            _ => return Ok(Some("Usage: /msg <USER|G:GROUP> <TEXT>".into())),
+        },
+        "/live" => match split_word(rest) {
+            (receiver, msg) if !receiver.is_empty() && !msg.is_empty() => {
+                RequestBody::Send(receiver.into(), msg.into(), true)
+            }
+            _ => return Ok(Some("Usage: /live <USER|G:GROUP> <TEXT>".into())),
+        },
+        "/group" => match parse_group(rest) {
+            Some(body) => body,
+            None => {
+                return Ok(Some(
+                    "Usage: /group <create|add|remove|destroy|role> ...".into(),
+                ));
+            }
+        },
+        "/who" => {
+            let group = split_args::<1>(rest).map(|[group]| group);
+            let users = client.who(group).await?;
+            return Ok(Some(match users.is_empty() {
+                true => "No user online".into(),
+                false => format!("Online: {}", users.join(", ")),
+            }));
+        }
+        "/whois" => match split_args::<1>(rest) {
+            Some([name]) => return Ok(Some(format_user_info(client.whois(name).await?))),
+            None => return Ok(Some("Usage: /whois <USER>".into())),
+        },
+        "/away" => RequestBody::Presence(Presence::Away),
+        "/back" => RequestBody::Presence(Presence::Online),
+        "/subscribe" => match split_args::<1>(rest) {
+            Some([name]) => RequestBody::Subscribe(name),
+            None => return Ok(Some("Usage: /subscribe <USER>".into())),
+        },
+        "/unsubscribe" => match split_args::<1>(rest) {
+            Some([name]) => RequestBody::Unsubscribe(name),
+            None => return Ok(Some("Usage: /unsubscribe <USER>".into())),
+        },
+        "/history" => {
+            let (target, before) = split_word(rest);
+            let before = match before {
+                "" => None,
+                before => match before.parse() {
+                    Ok(before) => Some(before),
+                    Err(_) => return Ok(Some("Usage: /history <USER|G:GROUP> [BEFORE]".into())),
+                },
+            };
+            if target.is_empty() {
+                return Ok(Some("Usage: /history <USER|G:GROUP> [BEFORE]".into()));
+            }
+
+            let entries = client.history(target, before, None).await?;
+            return Ok(Some(match entries.is_empty() {
+                true => "No message found".into(),
+                false => entries
+                    .into_iter()
+                    .map(format_history)
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            }));
+        }
+        "/block" => match split_word(rest) {
+            (name, "") if !name.is_empty() => RequestBody::Block(name.into(), BlockPolicy::Silent),
+            (name, "reject") if !name.is_empty() => {
+                RequestBody::Block(name.into(), BlockPolicy::Reject)
+            }
+            _ => return Ok(Some("Usage: /block <USER> [reject]".into())),
+        },
+        "/unblock" => match split_args::<1>(rest) {
+            Some([name]) => RequestBody::Unblock(name),
+            None => return Ok(Some("Usage: /unblock <USER>".into())),
+        },
+        "/blocks" => {
+            let blocks = client.blocks().await?;
+            return Ok(Some(match blocks.is_empty() {
+                true => "No user blocked".into(),
+                false => blocks
+                    .into_iter()
+                    .map(|entry| format!("{} ({})", entry.name, entry.policy))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            }));
+        }
+        "/kick" => match split_word(rest) {
+            ("", _) => return Ok(Some("Usage: /kick <USER> [REASON]".into())),
+            (name, "") => RequestBody::Kick(name.into(), None),
+            (name, reason) => RequestBody::Kick(name.into(), Some(reason.into())),
+        },
+        "/ban" => match parse_moderation(rest) {
+            Some((name, duration)) => RequestBody::Ban(name, duration),
+            None => return Ok(Some("Usage: /ban <USER> [SECONDS]".into())),
+        },
+        "/unban" => match split_args::<1>(rest) {
+            Some([name]) => RequestBody::Unban(name),
+            None => return Ok(Some("Usage: /unban <USER>".into())),
+        },
+        "/mute" => match parse_moderation(rest) {
+            Some((name, duration)) => RequestBody::Mute(name, duration),
+            None => return Ok(Some("Usage: /mute <USER> [SECONDS]".into())),
+        },
+        "/unmute" => match split_args::<1>(rest) {
+            Some([name]) => RequestBody::Unmute(name),
+            None => return Ok(Some("Usage: /unmute <USER>".into())),
+        },
+        "/announce" => match rest.trim() {
+            "" => return Ok(Some("Usage: /announce <TEXT>".into())),
+            msg => RequestBody::Announce(msg.into()),
+        },
+        _ => return Ok(Some(format!("Unknown command {}, try /help", cmd))),
+    };
+
+    let resp = client.request(body).await?;
+    Ok(Some(match resp.body {
+        ResponseBody::Success(Some(msg)) => format!("OK: {}", msg),
+        ResponseBody::Success(None) => "OK".into(),
+        ResponseBody::Error(err) => format!("Failed: {}", err),
+        _ => resp.to_string(),
+    }))
+}
+
+fn parse_group(args: &str) -> Option<RequestBody> {
+    let (cmd, rest) = split_word(args);
+    let body = match cmd {
+        "create" => {
+            let [group] = split_args::<1>(rest)?;
+            RequestBody::GroupCreate(group)
+        }
+        "add" => {
+            let [group, user] = split_args::<2>(rest)?;
+            RequestBody::GroupAdd(group, user)
+        }
+        "remove" => {
+            let [group, user] = split_args::<2>(rest)?;
+            RequestBody::GroupRemove(group, user)
+        }
+        "destroy" => {
+            let [group] = split_args::<1>(rest)?;
+            RequestBody::GroupDestroy(group)
+        }
+        "role" => {
+            let [group, user, role] = split_args::<3>(rest)?;
+            let role = match role.as_str() {
+                "owner" => GroupRole::Owner,
+                "moderator" => GroupRole::Moderator,
+                "member" => GroupRole::Member,
+                _ => return None,
+            };
+            RequestBody::GroupRole(group, user, role)
+        }
+        _ => return None,
+    };
+
+    Some(body)
+}
+
+/// Split the first word from a line.
+fn split_word(line: &str) -> (&str, &str) {
+    match line.trim().split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim_start()),
+        None => (line.trim(), ""),
+    }
+}
+
+/// Parse a user name with an optional duration in seconds.
+fn parse_moderation(args: &str) -> Option<(String, Option<u64>)> {
+    match split_word(args) {
+        ("", _) => None,
+        (name, "") => Some((name.into(), None)),
+        (name, duration) => duration.parse().ok().map(|d| (name.into(), Some(d))),
+    }
+}
+
+/// Split exactly `N` words from a line.
+fn split_args<const N: usize>(line: &str) -> Option<[String; N]> {
+    let words: Vec<String> = line.split_whitespace().map(String::from).collect();
+    words.try_into().ok()
+}
+
+fn format_push(resp: Response) -> String {
+    match resp.body {
+        ResponseBody::Recv(sender, msg, _) => format!("[{}] {}", sender, msg),
+        ResponseBody::Presence(name, presence) => format!("* {} is {}", name, presence),
+        ResponseBody::Sent(receiver, msg) => format!("[-> {}] {}", receiver, msg),
+        ResponseBody::Hello(version, _, _) => format!("* Server speaks protocol {}", version),
+        ResponseBody::Notice(level, msg) => format!("* {}: {}", level, msg),
+        _ => resp.to_string(),
+    }
+}
+
+fn format_history(entry: HistoryEntry) -> String {
+    format!(
+        "#{} [{} -> {}] {}",
+        entry.id, entry.sender, entry.target, entry.msg
+    )
+}
+
+fn format_user_info(info: UserInfo) -> String {
+    let state = match (info.presence, info.last_seen) {
+        (Presence::Offline, Some(time)) => format!("Offline, last seen at {} (UNIX time)", time),
+        (presence, _) => presence.to_string(),
+    };
+
+    match info.groups.is_empty() {
+        true => format!("{}: {}", info.name, state),
+        false => format!(
+            "{}: {}, groups: {}",
+            info.name,
+            state,
+            info.groups.join(", ")
+        ),
+    }
+}
+
+fn readline_error(err: ReadlineError) -> QuipError {
+    QuipError::Unknown(format!("Terminal error: {}", err))
+}
@@ -0,0 +1,21 @@
+use quip::{QuipResult, data::hash_password};
+use std::io::{self, BufRead};
+
+/// Print password hashes for the user data file, passwords are read from
+/// arguments, or from stdin line by line if there is no argument.
+fn main() -> QuipResult<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if !args.is_empty() {
+        for password in args {
+            println!("{}", hash_password(&password)?);
+        }
+        return Ok(());
+    }
+
+    for password in io::stdin().lock().lines() {
+        println!("{}", hash_password(&password?)?);
+    }
+
+    Ok(())
+}
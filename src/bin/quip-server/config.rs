@@ -0,0 +1,305 @@
+//! Configuration of `quip-server`, loaded from file and command line.
+
+use clap::{Parser, ValueEnum};
+use log::LevelFilter;
+use quip::{
+    QuipError, QuipResult,
+    server::config::{OfflineOverflow, ServerConfig},
+};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// Quip server.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Args {
+    /// Config file, in TOML or JSON (with `.json` extension).
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+
+    /// Listener kind.
+    #[arg(long)]
+    pub listener: Option<ListenerKind>,
+
+    /// Address to listen, which is the read address with two-socket SSL/TLS.
+    #[arg(long)]
+    pub addr: Option<String>,
+
+    /// Address to write with SSL/TLS, which enables the two-socket mode.
+    #[arg(long)]
+    pub write_addr: Option<String>,
+
+    /// PKCS#12 identity file for SSL/TLS.
+    #[arg(long)]
+    pub identity: Option<PathBuf>,
+
+    /// Password of the PKCS#12 identity file.
+    #[arg(long)]
+    pub identity_password: Option<String>,
+
+    /// Backend kind.
+    #[arg(long)]
+    pub backend: Option<BackendKind>,
+
+    /// User data file in JSON, which seeds the file backend.
+    #[arg(long)]
+    pub data: Option<PathBuf>,
+
+    /// Directory of the file backend.
+    #[arg(long)]
+    pub data_dir: Option<PathBuf>,
+
+    /// Log level, `RUST_LOG` takes precedence.
+    #[arg(long)]
+    pub log_level: Option<LevelFilter>,
+
+    /// Allow users to register.
+    #[arg(long)]
+    pub allow_register: Option<bool>,
+
+    /// Minimum length of passwords.
+    #[arg(long)]
+    pub min_password_len: Option<usize>,
+
+    /// Seconds to wait for connections when shutting down.
+    #[arg(long)]
+    pub shutdown_timeout: Option<u64>,
+
+    /// Seconds to keep a dropped connection for resuming, 0 to disable.
+    #[arg(long)]
+    pub resume_grace: Option<u64>,
+
+    /// Maximum length of request lines in bytes, 0 if unlimited.
+    #[arg(long)]
+    pub max_line_len: Option<usize>,
+
+    /// Seconds for new connections to handshake and login, 0 to disable.
+    #[arg(long)]
+    pub login_timeout: Option<u64>,
+
+    /// Seconds without requests before pinging a connection, 0 to disable.
+    #[arg(long)]
+    pub idle_timeout: Option<u64>,
+
+    /// Requests per second of a connection, 0 if unlimited.
+    #[arg(long)]
+    pub request_rate: Option<u32>,
+
+    /// Requests allowed in a burst.
+    #[arg(long)]
+    pub request_burst: Option<u32>,
+
+    /// Messages per second of a connection, 0 if unlimited.
+    #[arg(long)]
+    pub message_rate: Option<u32>,
+
+    /// Messages allowed in a burst.
+    #[arg(long)]
+    pub message_burst: Option<u32>,
+
+    /// Connection attempts per second from an IP address, 0 if unlimited.
+    #[arg(long)]
+    pub connect_rate: Option<u32>,
+
+    /// Connection attempts allowed in a burst.
+    #[arg(long)]
+    pub connect_burst: Option<u32>,
+
+    /// Rate limited requests before disconnecting, 0 to never disconnect.
+    #[arg(long)]
+    pub max_violations: Option<u32>,
+
+    /// Maximum messages kept in history of a conversation, 0 to disable.
+    #[arg(long)]
+    pub history_limit: Option<usize>,
+
+    /// Seconds to keep messages in history, 0 to keep them forever.
+    #[arg(long)]
+    pub history_ttl: Option<u64>,
+
+    /// Maximum messages cached for an offline user, 0 if unlimited.
+    #[arg(long)]
+    pub offline_limit: Option<usize>,
+
+    /// Seconds to cache messages for an offline user, 0 to cache them forever.
+    #[arg(long)]
+    pub offline_ttl: Option<u64>,
+
+    /// What to do when messages cached for an offline user reach the limit.
+    #[arg(long)]
+    pub offline_overflow: Option<OfflineOverflow>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ListenerKind {
+    Tcp,
+    Tls,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    Memory,
+    File,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ListenerConfig {
+    pub kind: ListenerKind,
+    pub addr: String,
+    pub write_addr: Option<String>,
+    pub identity: Option<PathBuf>,
+    pub identity_password: String,
+}
+
+impl Default for ListenerConfig {
+    fn default() -> Self {
+        Self {
+            kind: ListenerKind::Tcp,
+            addr: "0.0.0.0:1145".into(),
+            write_addr: None,
+            identity: None,
+            identity_password: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct BackendConfig {
+    pub kind: BackendKind,
+    pub data: Option<PathBuf>,
+    pub dir: Option<PathBuf>,
+}
+
+impl Default for BackendConfig {
+    fn default() -> Self {
+        Self {
+            kind: BackendKind::Memory,
+            data: None,
+            dir: None,
+        }
+    }
+}
+
+/// Full configuration of `quip-server`.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub log_level: LevelFilter,
+    pub listener: ListenerConfig,
+    pub backend: BackendConfig,
+    pub server: ServerConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            log_level: LevelFilter::Info,
+            listener: ListenerConfig::default(),
+            backend: BackendConfig::default(),
+            server: ServerConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Load config file if provided, and override it with arguments.
+    pub fn load(args: Args) -> QuipResult<Self> {
+        let mut config = match &args.config {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+
+        let listener = &mut config.listener;
+        override_with(&mut listener.kind, args.listener);
+        override_with(&mut listener.addr, args.addr);
+        override_with(&mut listener.write_addr, args.write_addr.map(Some));
+        override_with(&mut listener.identity, args.identity.map(Some));
+        override_with(&mut listener.identity_password, args.identity_password);
+
+        let backend = &mut config.backend;
+        override_with(&mut backend.kind, args.backend);
+        override_with(&mut backend.data, args.data.map(Some));
+        override_with(&mut backend.dir, args.data_dir.map(Some));
+
+        override_with(&mut config.log_level, args.log_level);
+
+        let server = &mut config.server;
+        override_with(&mut server.allow_register, args.allow_register);
+        override_with(&mut server.min_password_len, args.min_password_len);
+        override_with(&mut server.shutdown_timeout, args.shutdown_timeout);
+        override_with(&mut server.resume_grace, args.resume_grace);
+        override_with(&mut server.max_line_len, args.max_line_len);
+        override_with(&mut server.login_timeout, args.login_timeout);
+        override_with(&mut server.idle_timeout, args.idle_timeout);
+        override_with(&mut server.request_rate, args.request_rate);
+        override_with(&mut server.request_burst, args.request_burst);
+        override_with(&mut server.message_rate, args.message_rate);
+        override_with(&mut server.message_burst, args.message_burst);
+        override_with(&mut server.connect_rate, args.connect_rate);
+        override_with(&mut server.connect_burst, args.connect_burst);
+        override_with(&mut server.max_violations, args.max_violations);
+        override_with(&mut server.history_limit, args.history_limit);
+        override_with(&mut server.history_ttl, args.history_ttl);
+        override_with(&mut server.offline_limit, args.offline_limit);
+        override_with(&mut server.offline_ttl, args.offline_ttl);
+        override_with(&mut server.offline_overflow, args.offline_overflow);
+
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> QuipResult<Self> {
+        let contents = std::fs::read_to_string(path)?;
+
+        let res = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&contents).map_err(|err| err.to_string()),
+            _ => toml::from_str(&contents).map_err(|err| err.to_string()),
+        };
+
+        res.map_err(|err| QuipError::Config(format!("{}: {}", path.display(), err)))
+    }
+
+    /// Check the config, all problems are reported together.
+    pub fn validate(&self) -> QuipResult<()> {
+        let mut errors = Vec::new();
+
+        if self.listener.kind == ListenerKind::Tls {
+            match &self.listener.identity {
+                Some(path) if !path.is_file() => errors.push(format!(
+                    "listener.identity {} is not a file",
+                    path.display()
+                )),
+                Some(_) => {}
+                None => errors.push("listener.identity is required for SSL/TLS listener".into()),
+            }
+        }
+
+        if let Some(path) = &self.backend.data
+            && !path.is_file()
+        {
+            errors.push(format!("backend.data {} is not a file", path.display()));
+        }
+
+        if self.backend.kind == BackendKind::File && self.backend.dir.is_none() {
+            errors.push("backend.dir is required for file backend".into());
+        }
+
+        if self.server.min_password_len == 0 {
+            errors.push("server.min_password_len should be greater than 0".into());
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(QuipError::Config(errors.join("; "))),
+        }
+    }
+}
+
+fn override_with<T>(value: &mut T, arg: Option<T>) {
+    if let Some(arg) = arg {
+        *value = arg;
+    }
+}
@@ -0,0 +1,164 @@
+mod config;
+
+use crate::config::{Args, BackendKind, Config, ListenerKind};
+use clap::Parser;
+use log::{error, info, warn};
+use native_tls::Identity;
+use quip::{
+    QuipError, QuipResult,
+    data::BackendData,
+    server::{
+        self,
+        backend::{Backend, FileBackend, MemoryBackend},
+        listener::{Handshake, Listener, TcpListener, TlsDuplexListener, TlsListener},
+        shutdown::Shutdown,
+    },
+};
+use std::process::ExitCode;
+
+/// Listener selected by config.
+enum AnyListener {
+    Tcp(TcpListener),
+    Tls(TlsListener),
+    TlsDuplex(TlsDuplexListener),
+}
+
+impl Listener for AnyListener {
+    async fn accept(&self) -> QuipResult<Handshake> {
+        match self {
+            AnyListener::Tcp(listener) => listener.accept().await,
+            AnyListener::Tls(listener) => listener.accept().await,
+            AnyListener::TlsDuplex(listener) => listener.accept().await,
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    // Errors are printed once here, instead of returned to the runtime.
+    let config = match Config::load(Args::parse()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    env_logger::Builder::new()
+        .filter_level(config.log_level)
+        .parse_default_env()
+        .init();
+
+    if let Err(err) = run(config).await {
+        error!("{}", err);
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
+}
+
+async fn run(config: Config) -> QuipResult<()> {
+    config.validate()?;
+
+    // Load everything before binding, so that errors are reported early.
+    let identity = match &config.listener.identity {
+        Some(path) if config.listener.kind == ListenerKind::Tls => {
+            let identity = tokio::fs::read(path).await?;
+            Some(Identity::from_pkcs12(
+                &identity,
+                &config.listener.identity_password,
+            )?)
+        }
+        _ => None,
+    };
+
+    let data = match &config.backend.data {
+        Some(path) => BackendData::from_file(path.to_string_lossy()).await?,
+        None => {
+            warn!("No user data file is provided, starting with no user");
+            BackendData::new(vec![], vec![])
+        }
+    };
+
+    let retention = config.server.history_retention();
+    let policy = config.server.offline_policy();
+    match (config.backend.kind, &config.backend.dir) {
+        (BackendKind::Memory, _) => {
+            let backend = MemoryBackend::from_data(data)?
+                .with_history_retention(retention)
+                .with_offline_policy(policy);
+            serve(config, identity, backend).await
+        }
+        (BackendKind::File, Some(dir)) => {
+            let backend = FileBackend::open_with(dir, data, retention, policy).await?;
+            serve(config, identity, backend).await
+        }
+        (BackendKind::File, None) => Err(QuipError::Config("backend.dir is required".into())),
+    }
+}
+
+/// Bind the listener after the backend is loaded, and serve until shutdown.
+async fn serve<B>(config: Config, identity: Option<Identity>, backend: B) -> QuipResult<()>
+where
+    B: Backend + 'static,
+{
+    let addr = &config.listener.addr;
+    let (rate, burst) = (config.server.connect_rate, config.server.connect_burst);
+    let listener = match (config.listener.kind, identity, &config.listener.write_addr) {
+        (ListenerKind::Tcp, _, _) => {
+            AnyListener::Tcp(TcpListener::bind(addr).await?.with_rate_limit(rate, burst))
+        }
+        (ListenerKind::Tls, Some(identity), Some(write_addr)) => AnyListener::Tls(
+            TlsListener::bind(addr, write_addr, identity)
+                .await?
+                .with_rate_limit(rate, burst),
+        ),
+        (ListenerKind::Tls, Some(identity), None) => AnyListener::TlsDuplex(
+            TlsDuplexListener::bind(addr, identity)
+                .await?
+                .with_rate_limit(rate, burst),
+        ),
+        (ListenerKind::Tls, None, _) => {
+            return Err(QuipError::Config("listener.identity is required".into()));
+        }
+    };
+
+    let shutdown = Shutdown::new();
+    tokio::spawn(wait_signal(shutdown.clone()));
+
+    server::run(listener, backend, config.server, shutdown).await
+}
+
+/// Trigger shutdown on SIGINT, or SIGTERM on unix.
+async fn wait_signal(shutdown: Shutdown) {
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(err) => {
+                warn!("Failed to listen SIGTERM: {}", err);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        res = tokio::signal::ctrl_c() => {
+            if let Err(err) = res {
+                warn!("Failed to listen SIGINT: {}", err);
+                return;
+            }
+        }
+        _ = terminate => {}
+    }
+
+    info!("Signal received, shutting down");
+    shutdown.trigger();
+}
@@ -0,0 +1,560 @@
+//! Async client for Quip protocol.
+
+use crate::{
+    QuipError, QuipResult,
+    io::{
+        DynamicQuipIO, DynamicQuipInput, DynamicQuipOutput,
+        buffer::{QuipBufReader, QuipBufWriter},
+        tcp::QuipTcpStream,
+        tls::{QuipTlsDuplexStream, QuipTlsStream},
+    },
+    request::{Request, RequestBody},
+    response::{
+        BlockEntry, BlockPolicy, Capability, HistoryEntry, Presence, Response, ResponseBody,
+        UserInfo, decode_list,
+    },
+};
+use futures_core::Stream;
+use log::warn;
+use native_tls::TlsConnector as NativeTlsConnector;
+use std::{
+    collections::BTreeMap,
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    task::{Context, Poll},
+};
+use tokio::{
+    net::{TcpStream, ToSocketAddrs},
+    sync::{Mutex, mpsc, oneshot},
+    task::JoinHandle,
+};
+use tokio_native_tls::TlsConnector;
+
+/// Requests waiting for responses by sequence of their tags, `None` if the
+/// connection was closed.
+type PendingMap = Arc<Mutex<Option<BTreeMap<u64, oneshot::Sender<Response>>>>>;
+
+/// Prefix of tags generated by [`Client`], followed by sequence of the
+/// request.
+const TAG_PREFIX: &str = "C";
+
+/// Stream of untagged responses pushed by server, such as `* Recv`.
+#[derive(Debug)]
+pub struct PushStream(mpsc::UnboundedReceiver<Response>);
+
+impl PushStream {
+    /// Receive next pushed response, `None` if the connection was closed.
+    pub async fn recv(&mut self) -> Option<Response> {
+        self.0.recv().await
+    }
+}
+
+impl Stream for PushStream {
+    type Item = Response;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().0.poll_recv(cx)
+    }
+}
+
+/// Quip client.
+///
+/// Every request is sent with a unique tag, and the future returned by
+/// [`Client::request`] resolves when the response with the same tag arrives.
+/// Untagged responses are sent to the [`PushStream`] created with the client.
+///
+/// Server can not tag errors of requests it failed to read, e.g. lines longer
+/// than the limit in `* Hello`, so an untagged `* Error` resolves the oldest
+/// pending request. Requests longer than the limit are rejected before sent.
+pub struct Client {
+    writer: Mutex<QuipBufWriter<DynamicQuipOutput>>,
+    pending: PendingMap,
+    next_tag: AtomicU64,
+    max_line: Arc<AtomicUsize>,
+    reader: JoinHandle<()>,
+}
+
+impl Client {
+    /// Create a [`Client`] with any [`QuipIO`](crate::io::QuipIO).
+    pub fn new(io: DynamicQuipIO) -> (Self, PushStream) {
+        let (rx, tx) = io.duplex();
+        Self::from_duplex(rx, tx)
+    }
+
+    /// Create a [`Client`] with separated input and output.
+    pub fn from_duplex(rx: DynamicQuipInput, tx: DynamicQuipOutput) -> (Self, PushStream) {
+        let pending = Arc::new(Mutex::new(Some(BTreeMap::new())));
+        let max_line = Arc::new(AtomicUsize::new(0));
+        let (push_tx, push_rx) = mpsc::unbounded_channel();
+
+        let reader = tokio::spawn(serve_read(
+            QuipBufReader::new(rx),
+            pending.clone(),
+            max_line.clone(),
+            push_tx,
+        ));
+
+        let client = Self {
+            writer: Mutex::new(QuipBufWriter::new(tx)),
+            pending,
+            next_tag: AtomicU64::new(0),
+            max_line,
+            reader,
+        };
+
+        (client, PushStream(push_rx))
+    }
+
+    /// Connect to a server with TCP.
+    pub async fn connect_tcp(addr: impl ToSocketAddrs) -> QuipResult<(Self, PushStream)> {
+        let socket = TcpStream::connect(addr).await?;
+        Ok(Self::new(Box::new(QuipTcpStream::new(socket))))
+    }
+
+    /// Connect to a server with SSL/TLS over a single socket, see
+    /// [`TlsDuplexListener`](crate::server::listener::TlsDuplexListener).
+    pub async fn connect_tls_duplex(
+        addr: impl ToSocketAddrs,
+        domain: &str,
+        connector: NativeTlsConnector,
+    ) -> QuipResult<(Self, PushStream)> {
+        let stream = TlsConnector::from(connector)
+            .connect(domain, TcpStream::connect(addr).await?)
+            .await?;
+
+        Ok(Self::new(Box::new(QuipTlsDuplexStream::new(stream))))
+    }
+
+    /// Connect to a server with SSL/TLS, which requires two sockets.
+    ///
+    /// The `read_addr` and `write_addr` are the addresses which server reads
+    /// from and writes to, see [`TlsListener`](crate::server::listener::TlsListener).
+    pub async fn connect_tls(
+        read_addr: impl ToSocketAddrs,
+        write_addr: impl ToSocketAddrs,
+        domain: &str,
+        connector: NativeTlsConnector,
+    ) -> QuipResult<(Self, PushStream)> {
+        let connector = TlsConnector::from(connector);
+
+        let tx = connector
+            .connect(domain, TcpStream::connect(read_addr).await?)
+            .await?;
+        let rx = connector
+            .connect(domain, TcpStream::connect(write_addr).await?)
+            .await?;
+
+        Ok(Self::new(Box::new(QuipTlsStream::new(rx, tx))))
+    }
+
+    /// Send a request, and wait for the response with the same tag.
+    ///
+    /// [`QuipError::TooLong`] is returned without sending if the request is
+    /// longer than the limit of server.
+    pub async fn request(&self, body: RequestBody) -> QuipResult<Response> {
+        let seq = self.next_tag.fetch_add(1, Ordering::Relaxed);
+        let request = Request::new(format!("{}{:04}", TAG_PREFIX, seq), body);
+
+        let max_line = self.max_line.load(Ordering::Relaxed);
+        if max_line > 0 && request.to_string().len() > max_line {
+            return Err(QuipError::TooLong(max_line));
+        }
+
+        let (tx, rx) = oneshot::channel();
+        match self.pending.lock().await.as_mut() {
+            Some(pending) => pending.insert(seq, tx),
+            None => return Err(QuipError::Disconnect),
+        };
+
+        let res = self.writer.lock().await.write_request(request).await;
+        if let Err(err) = res {
+            if let Some(pending) = self.pending.lock().await.as_mut() {
+                pending.remove(&seq);
+            }
+            return Err(err);
+        }
+
+        rx.await.map_err(|_| QuipError::Disconnect)
+    }
+
+    /// Send a request, and get the message of `Success` response.
+    ///
+    /// `Error` response is returned as [`QuipError::Response`].
+    pub async fn call(&self, body: RequestBody) -> QuipResult<Option<String>> {
+        match self.request(body).await?.body {
+            ResponseBody::Success(msg) => Ok(msg),
+            ResponseBody::Error(err) => Err(QuipError::Response(err)),
+            body => Err(QuipError::Parse(format!("Unexpected response {:?}", body))),
+        }
+    }
+
+    /// Enable protocol extensions, the enabled ones are returned.
+    ///
+    /// The greeting from server is received as `* Hello` in [`PushStream`].
+    pub async fn cap(&self, caps: &[Capability]) -> QuipResult<Vec<Capability>> {
+        let payload = self.call(RequestBody::Cap(caps.to_vec())).await?;
+        Ok(Capability::parse_list(decode_list(payload.as_deref())?))
+    }
+
+    /// Answer `* Ping` received in [`PushStream`], otherwise server closes the
+    /// connection.
+    pub async fn pong(&self, token: impl Into<String>) -> QuipResult<()> {
+        self.call(RequestBody::Pong(token.into())).await.map(|_| ())
+    }
+
+    /// Authenticate with user name and password, and get the token to resume
+    /// the session after reconnecting if `resume` is enabled by
+    /// [`Client::cap`].
+    pub async fn login(
+        &self,
+        name: impl Into<String>,
+        password: impl Into<String>,
+    ) -> QuipResult<Option<String>> {
+        let payload = self
+            .call(RequestBody::Login(name.into(), password.into()))
+            .await?;
+        Ok(session_token(payload.as_deref()))
+    }
+
+    /// Resume a dropped session with its token, and get the new token like
+    /// [`Client::login`].
+    pub async fn resume(&self, token: impl Into<String>) -> QuipResult<Option<String>> {
+        let payload = self.call(RequestBody::Resume(token.into())).await?;
+        Ok(session_token(payload.as_deref()))
+    }
+
+    /// Send message to a user, or a group with `G:` prefix.
+    pub async fn send(
+        &self,
+        receiver: impl Into<String>,
+        msg: impl Into<String>,
+    ) -> QuipResult<()> {
+        self.call(RequestBody::Send(receiver.into(), msg.into(), false))
+            .await
+            .map(|_| ())
+    }
+
+    /// Send message to a user or a group like [`Client::send`], but the
+    /// message is not cached for offline users.
+    pub async fn send_online(
+        &self,
+        receiver: impl Into<String>,
+        msg: impl Into<String>,
+    ) -> QuipResult<()> {
+        self.call(RequestBody::Send(receiver.into(), msg.into(), true))
+            .await
+            .map(|_| ())
+    }
+
+    /// List online users, optionally in a group.
+    pub async fn who(&self, group: Option<String>) -> QuipResult<Vec<String>> {
+        let payload = self.call(RequestBody::Who(group)).await?;
+        decode_list(payload.as_deref())
+    }
+
+    /// Query information of a user.
+    pub async fn whois(&self, name: impl Into<String>) -> QuipResult<UserInfo> {
+        match self.call(RequestBody::Whois(name.into())).await? {
+            Some(payload) => UserInfo::try_from(payload),
+            None => Err(QuipError::Parse("No user info found for Whois".into())),
+        }
+    }
+
+    /// Set presence of current user, which is `Online` or `Away`.
+    pub async fn set_presence(&self, presence: Presence) -> QuipResult<()> {
+        self.call(RequestBody::Presence(presence)).await.map(|_| ())
+    }
+
+    /// Receive presence of a user as `* Presence` in [`PushStream`].
+    pub async fn subscribe(&self, name: impl Into<String>) -> QuipResult<()> {
+        self.call(RequestBody::Subscribe(name.into()))
+            .await
+            .map(|_| ())
+    }
+
+    /// Stop receiving presence of a user.
+    pub async fn unsubscribe(&self, name: impl Into<String>) -> QuipResult<()> {
+        self.call(RequestBody::Unsubscribe(name.into()))
+            .await
+            .map(|_| ())
+    }
+
+    /// Acknowledge a message received as `* Recv`, so that it is not
+    /// delivered again after resuming.
+    pub async fn ack(&self, id: u64) -> QuipResult<()> {
+        self.call(RequestBody::Ack(id)).await.map(|_| ())
+    }
+
+    /// Block messages from a user, the policy decides whether the user is
+    /// told.
+    pub async fn block(&self, name: impl Into<String>, policy: BlockPolicy) -> QuipResult<()> {
+        self.call(RequestBody::Block(name.into(), policy))
+            .await
+            .map(|_| ())
+    }
+
+    /// Unblock messages from a user.
+    pub async fn unblock(&self, name: impl Into<String>) -> QuipResult<()> {
+        self.call(RequestBody::Unblock(name.into()))
+            .await
+            .map(|_| ())
+    }
+
+    /// List blocked users.
+    pub async fn blocks(&self) -> QuipResult<Vec<BlockEntry>> {
+        let payload = self.call(RequestBody::Blocks).await?;
+
+        decode_list(payload.as_deref())?
+            .into_iter()
+            .map(BlockEntry::try_from)
+            .collect()
+    }
+
+    /// Close all sessions of a user, admin only.
+    pub async fn kick(&self, name: impl Into<String>, reason: Option<String>) -> QuipResult<()> {
+        self.call(RequestBody::Kick(name.into(), reason))
+            .await
+            .map(|_| ())
+    }
+
+    /// Ban a user for seconds or forever, admin only.
+    pub async fn ban(&self, name: impl Into<String>, duration: Option<u64>) -> QuipResult<()> {
+        self.call(RequestBody::Ban(name.into(), duration))
+            .await
+            .map(|_| ())
+    }
+
+    /// Lift ban of a user, admin only.
+    pub async fn unban(&self, name: impl Into<String>) -> QuipResult<()> {
+        self.call(RequestBody::Unban(name.into())).await.map(|_| ())
+    }
+
+    /// Mute a user for seconds or forever, admin only.
+    pub async fn mute(&self, name: impl Into<String>, duration: Option<u64>) -> QuipResult<()> {
+        self.call(RequestBody::Mute(name.into(), duration))
+            .await
+            .map(|_| ())
+    }
+
+    /// Lift mute of a user, admin only.
+    pub async fn unmute(&self, name: impl Into<String>) -> QuipResult<()> {
+        self.call(RequestBody::Unmute(name.into()))
+            .await
+            .map(|_| ())
+    }
+
+    /// Send a notice to all online users, admin only.
+    pub async fn announce(&self, msg: impl Into<String>) -> QuipResult<()> {
+        self.call(RequestBody::Announce(msg.into()))
+            .await
+            .map(|_| ())
+    }
+
+    /// Query at most `limit` latest messages with a user or in a group, which
+    /// are older than `before` if provided.
+    pub async fn history(
+        &self,
+        target: impl Into<String>,
+        before: Option<u64>,
+        limit: Option<usize>,
+    ) -> QuipResult<Vec<HistoryEntry>> {
+        let payload = self
+            .call(RequestBody::History(target.into(), before, limit))
+            .await?;
+
+        decode_list(payload.as_deref())?
+            .into_iter()
+            .map(HistoryEntry::try_from)
+            .collect()
+    }
+
+    /// Logout and wait for server to close the connection.
+    pub async fn logout(&self) -> QuipResult<()> {
+        match self.request(RequestBody::Logout).await {
+            Ok(_) | Err(QuipError::Disconnect) => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+/// Get the token from payload of `Login` or `Resume`, i.e. `<NAME> <TOKEN>`.
+fn session_token(payload: Option<&str>) -> Option<String> {
+    decode_list(payload).ok()?.into_iter().nth(1)
+}
+
+/// Read task of a client, which dispatches responses by tag.
+async fn serve_read(
+    mut reader: QuipBufReader<DynamicQuipInput>,
+    pending: PendingMap,
+    max_line: Arc<AtomicUsize>,
+    push: mpsc::UnboundedSender<Response>,
+) {
+    loop {
+        let resp = match reader.read_response().await {
+            Ok(resp) => resp,
+            Err(QuipError::Parse(msg)) => {
+                warn!("Invalid response: {}", msg);
+                continue;
+            }
+            Err(_) => break,
+        };
+
+        let mut pending = pending.lock().await;
+        let tx = match (&resp.tag, &resp.body) {
+            (Some(tag), _) => {
+                let seq = tag
+                    .strip_prefix(TAG_PREFIX)
+                    .and_then(|seq| seq.parse::<u64>().ok());
+                match seq.and_then(|seq| pending.as_mut()?.remove(&seq)) {
+                    Some(tx) => tx,
+                    None => {
+                        warn!("No request with tag {}", tag);
+                        continue;
+                    }
+                }
+            }
+            // Requests are served in order, so the error belongs to the
+            // oldest pending one.
+            (None, ResponseBody::Error(_)) => {
+                match pending.as_mut().and_then(|pending| pending.pop_first()) {
+                    Some((_, tx)) => tx,
+                    None => {
+                        let _ = push.send(resp);
+                        continue;
+                    }
+                }
+            }
+            (None, body) => {
+                if let ResponseBody::Hello(_, limit, _) = body {
+                    max_line.store(*limit, Ordering::Relaxed);
+                }
+                let _ = push.send(resp);
+                continue;
+            }
+        };
+        let _ = tx.send(resp);
+    }
+
+    // Wake up all pending requests with disconnect error.
+    pending.lock().await.take();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{duplex, split};
+
+    #[tokio::test]
+    async fn test_client_request() {
+        let (client_io, server_io) = duplex(1024);
+        let (client_rx, client_tx) = split(client_io);
+        let (server_rx, server_tx) = split(server_io);
+
+        let (client, mut pushes) = Client::from_duplex(Box::new(client_rx), Box::new(client_tx));
+
+        let server = tokio::spawn(async move {
+            let mut reader = QuipBufReader::new(server_rx);
+            let mut writer = QuipBufWriter::new(server_tx);
+
+            let first = reader.read_request().await.unwrap();
+            let second = reader.read_request().await.unwrap();
+
+            // Respond out of order, with a push in between.
+            writer
+                .write_response(Response::error(
+                    Some(second.tag),
+                    crate::response::ResponseError::NotFound,
+                ))
+                .await
+                .unwrap();
+            writer
+                .write_response(Response::recv(None, "Scarlet", "Hello"))
+                .await
+                .unwrap();
+            writer
+                .write_response(Response::success(Some(first.tag), Some("Dessera".into())))
+                .await
+                .unwrap();
+        });
+
+        let (login, send) = tokio::join!(
+            client.login("Dessera", "Pass"),
+            client.send("Nobody", "Hello")
+        );
+        assert!(login.is_ok());
+        assert!(matches!(
+            send,
+            Err(QuipError::Response(
+                crate::response::ResponseError::NotFound
+            ))
+        ));
+
+        match pushes.recv().await.unwrap().body {
+            ResponseBody::Recv(name, msg, _) => {
+                assert_eq!(name, "Scarlet");
+                assert_eq!(msg, "Hello");
+            }
+            _ => panic!("Mismatched response, need Recv but others found"),
+        }
+
+        server.await.unwrap();
+        assert!(pushes.recv().await.is_none());
+        assert!(matches!(
+            client.request(RequestBody::Nop).await,
+            Err(QuipError::Disconnect)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_client_untagged_error() {
+        let (client_io, server_io) = duplex(1024);
+        let (client_rx, client_tx) = split(client_io);
+        let (server_rx, server_tx) = split(server_io);
+
+        let (client, mut pushes) = Client::from_duplex(Box::new(client_rx), Box::new(client_tx));
+
+        let server = tokio::spawn(async move {
+            let mut reader = QuipBufReader::new(server_rx).with_max_line(32);
+            let mut writer = QuipBufWriter::new(server_tx);
+
+            writer.write_response(Response::hello(32)).await.unwrap();
+
+            // Respond as if the request was not parsed.
+            reader.read_request().await.unwrap();
+            writer
+                .write_response(Response::error(
+                    None,
+                    crate::response::ResponseError::BadCommand,
+                ))
+                .await
+                .unwrap();
+        });
+
+        assert!(matches!(
+            pushes.recv().await.unwrap().body,
+            ResponseBody::Hello(_, 32, _)
+        ));
+        assert!(matches!(
+            client.send("Scarlet", "Hello ".repeat(8)).await,
+            Err(QuipError::TooLong(32))
+        ));
+        assert!(matches!(
+            client.request(RequestBody::Nop).await,
+            Ok(Response {
+                tag: None,
+                body: ResponseBody::Error(crate::response::ResponseError::BadCommand),
+            })
+        ));
+
+        server.await.unwrap();
+    }
+}
@@ -1,21 +1,124 @@
-use crate::{QuipError, QuipResult};
+use crate::{
+    QuipError, QuipResult,
+    response::{BlockPolicy, GroupRole},
+};
+use argon2::{
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+    password_hash::{SaltString, rand_core::OsRng},
+};
+use log::warn;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::{HashMap, HashSet},
-    sync::Arc,
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{Arc, LazyLock},
 };
 use tokio::{fs::File, io::AsyncReadExt};
 
-#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// Server-wide role of a user.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UserRole {
+    #[default]
+    Member,
+    /// Admins manage all groups.
+    Admin,
+}
+
+/// Expiry of a ban or mute which never expires.
+pub const FOREVER: u64 = u64::MAX;
+
+/// User record, the password is stored as a PHC string produced by
+/// [`hash_password`].
+///
+/// Messages from users in `blocks` are handled by the policy of each one. The
+/// user can not login until `banned_until`, or send messages until
+/// `muted_until`, both in seconds since UNIX epoch or [`FOREVER`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct User {
     pub name: String,
     pub password: String,
+    #[serde(default)]
+    pub role: UserRole,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub blocks: BTreeMap<String, BlockPolicy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub banned_until: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub muted_until: Option<u64>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl User {
+    /// Verify a plaintext password of the user.
+    pub fn verify(&self, password: &str) -> bool {
+        verify_password(&self.password, password)
+    }
+
+    /// Check if the user is banned at the time.
+    pub fn is_banned(&self, now: u64) -> bool {
+        self.banned_until.is_some_and(|until| now < until)
+    }
+
+    /// Check if the user is muted at the time.
+    pub fn is_muted(&self, now: u64) -> bool {
+        self.muted_until.is_some_and(|until| now < until)
+    }
+}
+
+/// Group record, the owner and moderators should be members of the group.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Group {
     pub name: String,
+    #[serde(default)]
+    pub owner: Option<String>,
     pub users: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub moderators: Vec<String>,
+}
+
+impl Group {
+    /// Role of a user in the group, `None` if the user is not a member.
+    pub fn role_of(&self, name: &str) -> Option<GroupRole> {
+        if !self.users.iter().any(|user| user == name) {
+            return None;
+        }
+
+        if self.owner.as_deref() == Some(name) {
+            Some(GroupRole::Owner)
+        } else if self.moderators.iter().any(|user| user == name) {
+            Some(GroupRole::Moderator)
+        } else {
+            Some(GroupRole::Member)
+        }
+    }
+
+    /// Check if a user is allowed to update the group, which ignores
+    /// server-wide roles.
+    pub fn allows(&self, operator: &str, action: GroupAction<'_>) -> bool {
+        match (action, self.role_of(operator)) {
+            (_, Some(GroupRole::Owner)) => true,
+            (GroupAction::Add(_), Some(GroupRole::Moderator)) => true,
+            (GroupAction::Remove(name), Some(_)) if name == operator => true,
+            (GroupAction::Remove(name), Some(GroupRole::Moderator)) => !matches!(
+                self.role_of(name),
+                Some(GroupRole::Owner | GroupRole::Moderator)
+            ),
+            _ => false,
+        }
+    }
+}
+
+/// Update of a group by a user.
+#[derive(Debug, Clone, Copy)]
+pub enum GroupAction<'a> {
+    /// Add a user, allowed for the owner and moderators.
+    Add(&'a str),
+    /// Remove a user, allowed for the owner, moderators removing members and
+    /// the user itself.
+    Remove(&'a str),
+    /// Destroy the group, allowed for the owner.
+    Destroy,
+    /// Set role of a member, allowed for the owner.
+    SetRole(&'a str, GroupRole),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -37,10 +140,93 @@ impl BackendData {
 
         fd.read_to_end(&mut contents).await?;
 
-        Ok(serde_json::from_slice::<Self>(contents.as_slice())?)
+        let mut data = serde_json::from_slice::<Self>(contents.as_slice())?;
+        data.upgrade_passwords()?;
+
+        Ok(data)
+    }
+
+    /// Insert a user, or replace the user with the same name.
+    pub fn upsert_user(&mut self, user: User) {
+        match self.users.iter_mut().find(|old| old.name == user.name) {
+            Some(old) => *old = user,
+            None => self.users.push(user),
+        }
+    }
+
+    /// Insert a group, or replace the group with the same name.
+    pub fn upsert_group(&mut self, group: Group) {
+        match self.groups.iter_mut().find(|old| old.name == group.name) {
+            Some(old) => *old = group,
+            None => self.groups.push(group),
+        }
+    }
+
+    /// Remove a group by name.
+    pub fn remove_group(&mut self, name: &str) {
+        self.groups.retain(|group| group.name != name);
+    }
+
+    /// Replace legacy plaintext passwords with password hashes.
+    pub fn upgrade_passwords(&mut self) -> QuipResult<()> {
+        for user in self.users.iter_mut() {
+            if PasswordHash::new(&user.password).is_err() {
+                warn!("Password of user {} is not hashed, upgrading", user.name);
+                user.password = hash_password(&user.password)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Hash a password with random salt, the result is a PHC string.
+pub fn hash_password(password: &str) -> QuipResult<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default().hash_password(password.as_bytes(), &salt)?;
+
+    Ok(hash.to_string())
+}
+
+/// Verify a password with a PHC string in constant time.
+pub fn verify_password(hash: &str, password: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(hash) => Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok(),
+        Err(_) => false,
     }
 }
 
+/// Hash verified for unknown users, so that they take as long as known ones.
+static DUMMY_HASH: LazyLock<String> =
+    LazyLock::new(|| hash_password("").expect("Failed to hash dummy password"));
+
+/// Hash a password in a blocking thread, see [`hash_password`].
+pub async fn hash_password_blocking(password: &str) -> QuipResult<String> {
+    let password = password.to_string();
+    tokio::task::spawn_blocking(move || hash_password(&password))
+        .await
+        .map_err(|err| QuipError::Unknown(err.to_string()))?
+}
+
+/// Verify a password in a blocking thread, see [`verify_password`].
+///
+/// A dummy hash is verified if `hash` is `None`, and the password is always
+/// incorrect.
+pub async fn verify_password_blocking(hash: Option<String>, password: &str) -> bool {
+    let password = password.to_string();
+    let verify = tokio::task::spawn_blocking(move || match hash {
+        Some(hash) => verify_password(&hash, &password),
+        None => {
+            verify_password(&DUMMY_HASH, &password);
+            false
+        }
+    });
+
+    verify.await.unwrap_or(false)
+}
+
 #[derive(Debug)]
 pub struct QueryGroup(pub Group, pub HashSet<Arc<User>>);
 
@@ -78,6 +264,18 @@ impl TryFrom<BackendData> for BackendQueryData {
     }
 }
 
+impl From<&BackendQueryData> for BackendData {
+    fn from(value: &BackendQueryData) -> Self {
+        let mut users: Vec<User> = value.users.values().map(|user| (**user).clone()).collect();
+        users.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut groups: Vec<Group> = value.groups.values().map(|group| group.0.clone()).collect();
+        groups.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Self { users, groups }
+    }
+}
+
 fn group_to_query(
     group: Group,
     grp_name: &str,
@@ -88,14 +286,126 @@ fn group_to_query(
         .iter()
         .map(|grp_user| match users.get(grp_user) {
             Some(user) => Ok(user.clone()),
-            None => {
-                return Err(QuipError::NotFound(format!(
-                    "User named {} required in {} does not exist",
-                    grp_user, grp_name
-                )));
-            }
+            None => Err(QuipError::NotFound(format!(
+                "User named {} required in {} does not exist",
+                grp_user, grp_name
+            ))),
         })
         .collect();
 
     Ok(QueryGroup(group, grp_users?))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_password() {
+        let hash = hash_password("Pass").unwrap();
+        assert!(hash.starts_with("$argon2"));
+        assert!(verify_password(&hash, "Pass"));
+        assert!(!verify_password(&hash, "Fail"));
+        assert!(!verify_password("Pass", "Pass"));
+    }
+
+    #[tokio::test]
+    async fn test_verify_password_blocking() {
+        let hash = hash_password_blocking("Pass").await.unwrap();
+        assert!(verify_password_blocking(Some(hash.clone()), "Pass").await);
+        assert!(!verify_password_blocking(Some(hash), "Fail").await);
+        assert!(!verify_password_blocking(None, "").await);
+    }
+
+    #[test]
+    fn test_upgrade_passwords() {
+        let hash = hash_password("Hashed").unwrap();
+        let mut data = BackendData::new(
+            vec![
+                User {
+                    name: "Dessera".into(),
+                    password: "Pass".into(),
+                    role: UserRole::Member,
+                    blocks: BTreeMap::new(),
+                    banned_until: None,
+                    muted_until: None,
+                },
+                User {
+                    name: "Scarlet".into(),
+                    password: hash.clone(),
+                    role: UserRole::Member,
+                    blocks: BTreeMap::new(),
+                    banned_until: None,
+                    muted_until: None,
+                },
+            ],
+            vec![],
+        );
+
+        data.upgrade_passwords().unwrap();
+        assert!(data.users[0].verify("Pass"));
+        assert_eq!(data.users[1].password, hash);
+    }
+
+    #[test]
+    fn test_user_role() {
+        let user: User =
+            serde_json::from_str(r#"{"name":"Dessera","password":"Pass","role":"admin"}"#).unwrap();
+        assert_eq!(user.role, UserRole::Admin);
+
+        let user: User = serde_json::from_str(r#"{"name":"Scarlet","password":"Pass"}"#).unwrap();
+        assert_eq!(user.role, UserRole::Member);
+
+        let json = serde_json::to_string(&User {
+            role: UserRole::Admin,
+            ..user
+        })
+        .unwrap();
+        assert_eq!(
+            json,
+            r#"{"name":"Scarlet","password":"Pass","role":"admin"}"#
+        );
+    }
+
+    #[test]
+    fn test_group_role() {
+        let group = Group {
+            name: "Team".into(),
+            owner: Some("Dessera".into()),
+            users: vec!["Dessera".into(), "Scarlet".into(), "Remilia".into()],
+            moderators: vec!["Scarlet".into()],
+        };
+
+        assert_eq!(group.role_of("Dessera"), Some(GroupRole::Owner));
+        assert_eq!(group.role_of("Scarlet"), Some(GroupRole::Moderator));
+        assert_eq!(group.role_of("Remilia"), Some(GroupRole::Member));
+        assert_eq!(group.role_of("Nobody"), None);
+    }
+
+    #[test]
+    fn test_group_allows() {
+        let group = Group {
+            name: "Team".into(),
+            owner: Some("Dessera".into()),
+            users: vec!["Dessera".into(), "Scarlet".into(), "Remilia".into()],
+            moderators: vec!["Scarlet".into()],
+        };
+
+        for action in [
+            GroupAction::Add("Sakuya"),
+            GroupAction::Destroy,
+            GroupAction::SetRole("Remilia", GroupRole::Moderator),
+        ] {
+            assert!(group.allows("Dessera", action));
+            assert!(!group.allows("Remilia", action));
+            assert!(!group.allows("Sakuya", action));
+        }
+
+        assert!(group.allows("Scarlet", GroupAction::Add("Sakuya")));
+        assert!(!group.allows("Scarlet", GroupAction::Destroy));
+        assert!(group.allows("Scarlet", GroupAction::Remove("Remilia")));
+        assert!(!group.allows("Scarlet", GroupAction::Remove("Dessera")));
+        assert!(group.allows("Remilia", GroupAction::Remove("Remilia")));
+        assert!(!group.allows("Remilia", GroupAction::Remove("Scarlet")));
+    }
+}
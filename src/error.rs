@@ -1,3 +1,5 @@
+use crate::response::ResponseError;
+
 #[derive(thiserror::Error, Debug)]
 pub enum QuipError {
     #[error("IO error: {0}")]
@@ -12,9 +14,18 @@ pub enum QuipError {
     #[error("Parse error: {0}")]
     Parse(String),
 
+    #[error("Line is longer than {0} bytes")]
+    TooLong(usize),
+
     #[error("Serialize error: {0}")]
     Serialize(#[from] serde_json::Error),
 
+    #[error("Password hash error: {0}")]
+    Hash(#[from] argon2::password_hash::Error),
+
+    #[error("Config error: {0}")]
+    Config(String),
+
     #[error("Duplicate error: {0}")]
     Duplicate(String),
 
@@ -24,6 +35,18 @@ pub enum QuipError {
     #[error("Authorize error: {0}")]
     Authorize(String),
 
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("Queue is full: {0}")]
+    QueueFull(String),
+
+    #[error("Rate limited: {0}")]
+    RateLimited(String),
+
+    #[error("Response error: {0}")]
+    Response(ResponseError),
+
     #[error("Unknown error: {0}")]
     Unknown(String),
 }
@@ -8,39 +8,89 @@ use crate::{
 };
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter};
 
-/// Reader for read [`Request`] from any [`QuipInput`].
-pub struct QuipBufReader<R>(BufReader<R>);
+/// Reader for read [`Request`] or [`Response`] from any [`QuipInput`].
+///
+/// Partial line is kept in the reader, so reading is cancel safe and can be
+/// used in `tokio::select!`. Lines longer than `max_line` are discarded.
+pub struct QuipBufReader<R> {
+    reader: BufReader<R>,
+    buffer: Vec<u8>,
+    max_line: usize,
+    discarding: bool,
+}
 
 impl<R> QuipBufReader<R>
 where
     R: QuipInput,
 {
     pub fn new(socket: R) -> Self {
-        Self(BufReader::new(socket))
+        Self {
+            reader: BufReader::new(socket),
+            buffer: Vec::new(),
+            max_line: 0,
+            discarding: false,
+        }
+    }
+
+    /// Set maximum length of lines in bytes, without the trailing `\n`, `0`
+    /// if unlimited.
+    pub fn with_max_line(mut self, max_line: usize) -> Self {
+        self.max_line = max_line;
+        self
     }
 
     /// Get [`Request`] from socket, terminate with `\n`.
     pub async fn read_request(&mut self) -> QuipResult<Request> {
-        let mut buffer = String::new();
-        let mut zero_cnt: usize = 0;
+        Request::try_from(self.read_line().await?)
+    }
+
+    /// Get [`Response`] from socket, terminate with `\n`.
+    pub async fn read_response(&mut self) -> QuipResult<Response> {
+        Response::try_from(self.read_line().await?)
+    }
+
+    /// Read a line, or [`QuipError::TooLong`] after the whole line exceeding
+    /// the limit is discarded.
+    async fn read_line(&mut self) -> QuipResult<String> {
+        // Read bytes are moved out of `BufReader` only when processed, so
+        // nothing is lost if cancelled.
         loop {
-            match self.0.read_line(&mut buffer).await? {
-                0 => {
-                    zero_cnt += 1;
-                    match zero_cnt {
-                        1 => continue,
-                        _ => return Err(QuipError::Disconnect),
-                    }
-                }
-                _ => break,
+            let available = self.reader.fill_buf().await?;
+            if available.is_empty() {
+                return Err(QuipError::Disconnect);
+            }
+
+            let (len, done) = match available.iter().position(|ch| *ch == b'\n') {
+                Some(pos) => (pos + 1, true),
+                None => (available.len(), false),
             };
+
+            if !self.discarding {
+                self.buffer.extend_from_slice(&available[..len]);
+
+                let content_len = self.buffer.len() - usize::from(done);
+                if self.max_line > 0 && content_len > self.max_line {
+                    self.buffer.clear();
+                    self.discarding = true;
+                }
+            }
+            self.reader.consume(len);
+
+            if done {
+                break;
+            }
+        }
+
+        if std::mem::take(&mut self.discarding) {
+            return Err(QuipError::TooLong(self.max_line));
         }
 
-        Request::try_from(buffer)
+        String::from_utf8(std::mem::take(&mut self.buffer))
+            .map_err(|_| QuipError::Parse("Line is not valid UTF-8".into()))
     }
 }
 
-/// Writer for write [`Response`] to any [`QuipOutput`].
+/// Writer for write [`Response`] or [`Request`] to any [`QuipOutput`].
 pub struct QuipBufWriter<W>(BufWriter<W>);
 
 impl<W> QuipBufWriter<W>
@@ -53,8 +103,71 @@ where
 
     /// Write [`Response`] to socket, end with `\n`.
     pub async fn write_response(&mut self, resp: Response) -> QuipResult<()> {
-        self.0.write_all(resp.to_string().as_bytes()).await?;
+        self.write_line(resp.to_string()).await
+    }
+
+    /// Write [`Request`] to socket, end with `\n`.
+    pub async fn write_request(&mut self, request: Request) -> QuipResult<()> {
+        self.write_line(request.to_string()).await
+    }
+
+    async fn write_line(&mut self, line: String) -> QuipResult<()> {
+        self.0.write_all(line.as_bytes()).await?;
         self.0.write_all("\n".as_bytes()).await?;
         Ok(self.0.flush().await?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::io::{AsyncWriteExt, duplex};
+
+    #[tokio::test]
+    async fn test_read_cancel_safe() {
+        let (mut client, server) = duplex(64);
+        let mut reader = QuipBufReader::new(server);
+
+        client.write_all(b"A000 Se").await.unwrap();
+        let res = tokio::time::timeout(Duration::from_millis(50), reader.read_request()).await;
+        assert!(res.is_err());
+
+        client.write_all(b"nd Dessera Hello\n").await.unwrap();
+        let request = reader.read_request().await.unwrap();
+        assert_eq!(request.to_string(), "A000 Send Dessera Hello");
+
+        drop(client);
+        assert!(matches!(
+            reader.read_request().await,
+            Err(QuipError::Disconnect)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_read_max_line() {
+        let (mut client, server) = duplex(64);
+        let mut reader = QuipBufReader::new(server).with_max_line(16);
+
+        // Longer than both the limit and the pipe, and never buffered.
+        let long = format!("A000 Send Dessera {}\n", "x".repeat(256));
+        let writer = tokio::spawn(async move {
+            client.write_all(long.as_bytes()).await.unwrap();
+            client.write_all(b"A001 Nop\n").await.unwrap();
+            client
+        });
+
+        assert!(matches!(
+            reader.read_request().await,
+            Err(QuipError::TooLong(16))
+        ));
+        let request = reader.read_request().await.unwrap();
+        assert_eq!(request.to_string(), "A001 Nop");
+
+        drop(writer.await.unwrap());
+        assert!(matches!(
+            reader.read_request().await,
+            Err(QuipError::Disconnect)
+        ));
+    }
+}
@@ -7,7 +7,7 @@ use std::{
     task::{Context, Poll},
 };
 use tokio::{
-    io::{AsyncRead, AsyncWrite, ReadBuf},
+    io::{AsyncRead, AsyncWrite, ReadBuf, split},
     net::TcpStream,
 };
 use tokio_native_tls::TlsStream;
@@ -66,3 +66,57 @@ impl QuipIO for QuipTlsStream {
         (Box::new(self.rx), Box::new(self.tx))
     }
 }
+
+/// Quip stream implementation with a single [`TlsStream`].
+///
+/// The stream is split by [`tokio::io::split`] for full duplex mode, which
+/// locks the stream for each read and write, so only one socket is needed.
+#[derive(Debug)]
+pub struct QuipTlsDuplexStream {
+    inner: TlsStream<TcpStream>,
+}
+
+impl QuipTlsDuplexStream {
+    pub fn new(inner: TlsStream<TcpStream>) -> Self {
+        Self { inner }
+    }
+}
+
+impl AsyncRead for QuipTlsDuplexStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let ptr = &mut self.get_mut().inner;
+        Pin::new(ptr).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for QuipTlsDuplexStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let ptr = &mut self.get_mut().inner;
+        Pin::new(ptr).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let ptr = &mut self.get_mut().inner;
+        Pin::new(ptr).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let ptr = &mut self.get_mut().inner;
+        Pin::new(ptr).poll_shutdown(cx)
+    }
+}
+
+impl QuipIO for QuipTlsDuplexStream {
+    fn duplex(self: Box<Self>) -> (DynamicQuipInput, DynamicQuipOutput) {
+        let (rx, tx) = split(self.inner);
+        (Box::new(rx), Box::new(tx))
+    }
+}
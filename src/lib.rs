@@ -1,3 +1,4 @@
+pub mod client;
 pub mod data;
 pub mod error;
 pub mod io;
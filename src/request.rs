@@ -1,26 +1,105 @@
 use crate::{
     QuipError, QuipResult,
+    response::{BlockPolicy, Capability, GroupRole, Presence},
     token::{detokenize, tokenize},
     unwrap_token,
 };
 use std::fmt;
 
+/// Prefix of group names in `Send` command.
+pub const GROUP_PREFIX: &str = "G:";
+
 /// General request body.
 ///
 /// A general request body may be one of the following requests:
 ///
 /// - `Send`: Send message to another user or group, i.e.
-///   `<TAG> Send <USER> <MESSAGE>` or `<TAG> Send G:<GROUP> <MESSAGE>`.
+///   `<TAG> Send <USER> <MESSAGE> [ONLINE]` or
+///   `<TAG> Send G:<GROUP> <MESSAGE> [ONLINE]`, with `ONLINE` the message is
+///   not cached for offline users.
 /// - `Login`: Authenticate connection with a user name, i.e.
-///   `<TAG> Login <NAME> <PASSWORD>`.
+///   `<TAG> Login <NAME> <PASSWORD>`, a token for `Resume` is returned with
+///   the `resume` capability.
+/// - `Resume`: Reattach a dropped connection with the token from `Login`, i.e.
+///   `<TAG> Resume <TOKEN>`.
+/// - `Register`: Create a new user, i.e. `<TAG> Register <NAME> <PASSWORD>`.
+/// - `Passwd`: Change password of current user, i.e.
+///   `<TAG> Passwd <OLD PASSWORD> <NEW PASSWORD>`.
 /// - `Logout`: Disconnect immediately, i.e. `<TAG> Logout`.
 /// - `Nop`: Do nothing, i.e. `<TAG> Nop`.
+/// - `GroupCreate`: Create a group owned by current user, i.e.
+///   `<TAG> GroupCreate <GROUP>`.
+/// - `GroupAdd`: Add a user to a group, i.e. `<TAG> GroupAdd <GROUP> <USER>`.
+/// - `GroupRemove`: Remove a user from a group, i.e.
+///   `<TAG> GroupRemove <GROUP> <USER>`.
+/// - `GroupDestroy`: Destroy a group, i.e. `<TAG> GroupDestroy <GROUP>`.
+/// - `GroupRole`: Set role of a member in a group, i.e.
+///   `<TAG> GroupRole <GROUP> <USER> <Owner|Moderator|Member>`.
+/// - `Who`: List online users, optionally in a group, i.e.
+///   `<TAG> Who` or `<TAG> Who <GROUP>`.
+/// - `Whois`: Query information of a user, i.e. `<TAG> Whois <USER>`.
+/// - `Presence`: Set presence of current user, i.e.
+///   `<TAG> Presence Online` or `<TAG> Presence Away`.
+/// - `Subscribe`: Receive presence of a user who shares no group with current
+///   user, i.e. `<TAG> Subscribe <USER>`.
+/// - `Unsubscribe`: Undo `Subscribe`, i.e. `<TAG> Unsubscribe <USER>`.
+/// - `Ack`: Acknowledge a received message, i.e. `<TAG> Ack <ID>`. A session
+///   keeps at most 1000 unacknowledged messages, the oldest one is dropped
+///   beyond it.
+/// - `History`: Query past messages with a user or in a group, i.e.
+///   `<TAG> History <USER|G:GROUP> [BEFORE <ID>] [LIMIT <N>]`, messages older
+///   than `BEFORE` are returned, the latest ones by default.
+/// - `Cap`: Enable protocol extensions announced in `Hello`, i.e.
+///   `<TAG> Cap <CAPABILITIES...>`, unknown ones are ignored and enabled ones
+///   are returned.
+/// - `Pong`: Answer `Ping` from server, i.e. `<TAG> Pong <TOKEN>`.
+/// - `Block`: Stop receiving messages from a user, i.e.
+///   `<TAG> Block <USER> [Silent|Reject]`, messages are dropped silently by
+///   default.
+/// - `Unblock`: Undo `Block`, i.e. `<TAG> Unblock <USER>`.
+/// - `Blocks`: List blocked users of current user, i.e. `<TAG> Blocks`.
+/// - `Kick`: Close all sessions of a user, which is admin only, i.e.
+///   `<TAG> Kick <USER> [REASON]`.
+/// - `Ban`: Kick a user and refuse its login for seconds, or forever without
+///   a duration, which is admin only, i.e. `<TAG> Ban <USER> [DURATION]`.
+/// - `Unban`: Undo `Ban`, which is admin only, i.e. `<TAG> Unban <USER>`.
+/// - `Mute`: Refuse messages from a user for seconds, or forever without a
+///   duration, which is admin only, i.e. `<TAG> Mute <USER> [DURATION]`.
+/// - `Unmute`: Undo `Mute`, which is admin only, i.e. `<TAG> Unmute <USER>`.
+/// - `Announce`: Send a notice to all online users, which is admin only, i.e.
+///   `<TAG> Announce <MSG>`.
 #[derive(Debug)]
 pub enum RequestBody {
-    Send(String, String),
+    Send(String, String, bool),
     Login(String, String),
+    Resume(String),
+    Register(String, String),
+    Passwd(String, String),
     Logout,
     Nop,
+    GroupCreate(String),
+    GroupAdd(String, String),
+    GroupRemove(String, String),
+    GroupDestroy(String),
+    GroupRole(String, String, GroupRole),
+    Who(Option<String>),
+    Whois(String),
+    Presence(Presence),
+    Subscribe(String),
+    Unsubscribe(String),
+    Ack(u64),
+    History(String, Option<u64>, Option<usize>),
+    Cap(Vec<Capability>),
+    Pong(String),
+    Block(String, BlockPolicy),
+    Unblock(String),
+    Blocks,
+    Kick(String, Option<String>),
+    Ban(String, Option<u64>),
+    Unban(String),
+    Mute(String, Option<u64>),
+    Unmute(String),
+    Announce(String),
 }
 
 /// General request, with tag for responses.
@@ -60,8 +139,18 @@ impl TryFrom<&str> for Request {
             "Send" => {
                 let name = unwrap_token!(tokens, "No name found for command Send");
                 let msg = unwrap_token!(tokens, "No message found for command Send");
+                let online = match tokens.next().as_deref() {
+                    Some("ONLINE") => true,
+                    Some(opt) => {
+                        return Err(QuipError::Parse(format!(
+                            "Unexpected option {} for command Send",
+                            opt
+                        )));
+                    }
+                    None => false,
+                };
 
-                RequestBody::Send(name, msg)
+                RequestBody::Send(name, msg, online)
             }
             "Login" => {
                 let name = unwrap_token!(tokens, "No name found for command Login");
@@ -69,8 +158,156 @@ impl TryFrom<&str> for Request {
 
                 RequestBody::Login(name, password)
             }
+            "Resume" => {
+                let token = unwrap_token!(tokens, "No token found for command Resume");
+
+                RequestBody::Resume(token)
+            }
+            "Register" => {
+                let name = unwrap_token!(tokens, "No name found for command Register");
+                let password = unwrap_token!(tokens, "No password found for command Register");
+
+                RequestBody::Register(name, password)
+            }
+            "Passwd" => {
+                let old = unwrap_token!(tokens, "No old password found for command Passwd");
+                let new = unwrap_token!(tokens, "No new password found for command Passwd");
+
+                RequestBody::Passwd(old, new)
+            }
             "Logout" => RequestBody::Logout,
             "Nop" => RequestBody::Nop,
+            "GroupCreate" => {
+                let group = unwrap_token!(tokens, "No group found for command GroupCreate");
+
+                RequestBody::GroupCreate(group)
+            }
+            "GroupAdd" => {
+                let group = unwrap_token!(tokens, "No group found for command GroupAdd");
+                let name = unwrap_token!(tokens, "No name found for command GroupAdd");
+
+                RequestBody::GroupAdd(group, name)
+            }
+            "GroupRemove" => {
+                let group = unwrap_token!(tokens, "No group found for command GroupRemove");
+                let name = unwrap_token!(tokens, "No name found for command GroupRemove");
+
+                RequestBody::GroupRemove(group, name)
+            }
+            "GroupDestroy" => {
+                let group = unwrap_token!(tokens, "No group found for command GroupDestroy");
+
+                RequestBody::GroupDestroy(group)
+            }
+            "GroupRole" => {
+                let group = unwrap_token!(tokens, "No group found for command GroupRole");
+                let name = unwrap_token!(tokens, "No name found for command GroupRole");
+                let role = unwrap_token!(tokens, "No role found for command GroupRole");
+
+                RequestBody::GroupRole(group, name, role.try_into()?)
+            }
+            "Who" => RequestBody::Who(tokens.next()),
+            "Whois" => {
+                let name = unwrap_token!(tokens, "No name found for command Whois");
+
+                RequestBody::Whois(name)
+            }
+            "Presence" => {
+                let presence = unwrap_token!(tokens, "No presence found for command Presence");
+
+                RequestBody::Presence(presence.try_into()?)
+            }
+            "Subscribe" => {
+                let name = unwrap_token!(tokens, "No name found for command Subscribe");
+
+                RequestBody::Subscribe(name)
+            }
+            "Unsubscribe" => {
+                let name = unwrap_token!(tokens, "No name found for command Unsubscribe");
+
+                RequestBody::Unsubscribe(name)
+            }
+            "Ack" => {
+                let id = unwrap_token!(tokens, "No ID found for command Ack");
+                let id = id
+                    .parse()
+                    .map_err(|_| QuipError::Parse(format!("{} is not a valid message ID", id)))?;
+
+                RequestBody::Ack(id)
+            }
+            "History" => {
+                let target = unwrap_token!(tokens, "No target found for command History");
+
+                let (mut before, mut limit) = (None, None);
+                while let Some(key) = tokens.next() {
+                    let value = unwrap_token!(tokens, format!("No value found for {}", key));
+                    let invalid = || QuipError::Parse(format!("{} is not a valid number", value));
+
+                    match key.as_str() {
+                        "BEFORE" => before = Some(value.parse().map_err(|_| invalid())?),
+                        "LIMIT" => limit = Some(value.parse().map_err(|_| invalid())?),
+                        _ => {
+                            return Err(QuipError::Parse(format!(
+                                "Unexpected option {} for command History",
+                                key
+                            )));
+                        }
+                    }
+                }
+
+                RequestBody::History(target, before, limit)
+            }
+            "Cap" => RequestBody::Cap(Capability::parse_list(tokens)),
+            "Pong" => {
+                let token = unwrap_token!(tokens, "No token found for command Pong");
+
+                RequestBody::Pong(token)
+            }
+            "Block" => {
+                let name = unwrap_token!(tokens, "No name found for command Block");
+                let policy = match tokens.next() {
+                    Some(policy) => policy.try_into()?,
+                    None => BlockPolicy::default(),
+                };
+
+                RequestBody::Block(name, policy)
+            }
+            "Unblock" => {
+                let name = unwrap_token!(tokens, "No name found for command Unblock");
+
+                RequestBody::Unblock(name)
+            }
+            "Blocks" => RequestBody::Blocks,
+            "Kick" => {
+                let name = unwrap_token!(tokens, "No name found for command Kick");
+
+                RequestBody::Kick(name, tokens.next())
+            }
+            "Ban" => {
+                let name = unwrap_token!(tokens, "No name found for command Ban");
+
+                RequestBody::Ban(name, parse_duration(tokens.next())?)
+            }
+            "Unban" => {
+                let name = unwrap_token!(tokens, "No name found for command Unban");
+
+                RequestBody::Unban(name)
+            }
+            "Mute" => {
+                let name = unwrap_token!(tokens, "No name found for command Mute");
+
+                RequestBody::Mute(name, parse_duration(tokens.next())?)
+            }
+            "Unmute" => {
+                let name = unwrap_token!(tokens, "No name found for command Unmute");
+
+                RequestBody::Unmute(name)
+            }
+            "Announce" => {
+                let msg = unwrap_token!(tokens, "No message found for command Announce");
+
+                RequestBody::Announce(msg)
+            }
             _ => return Err(QuipError::Parse(format!("Unexpected command {}", cmd))),
         };
 
@@ -78,13 +315,104 @@ impl TryFrom<&str> for Request {
     }
 }
 
+/// Parse an optional duration in seconds.
+fn parse_duration(value: Option<String>) -> QuipResult<Option<u64>> {
+    value
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| QuipError::Parse(format!("{} is not a valid duration", value)))
+        })
+        .transpose()
+}
+
 impl fmt::Display for Request {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arg;
+        let args: Vec<String>;
         let tokens = match &self.body {
-            RequestBody::Send(name, msg) => vec![&self.tag, "Send", name, msg],
+            RequestBody::Send(name, msg, false) => vec![&self.tag, "Send", name, msg],
+            RequestBody::Send(name, msg, true) => vec![&self.tag, "Send", name, msg, "ONLINE"],
             RequestBody::Login(name, password) => vec![&self.tag, "Login", name, password],
+            RequestBody::Resume(token) => vec![&self.tag, "Resume", token],
+            RequestBody::Register(name, password) => {
+                vec![&self.tag, "Register", name, password]
+            }
+            RequestBody::Passwd(old, new) => vec![&self.tag, "Passwd", old, new],
             RequestBody::Logout => vec![&self.tag, "Logout"],
             RequestBody::Nop => vec![&self.tag, "Nop"],
+            RequestBody::GroupCreate(group) => vec![&self.tag, "GroupCreate", group],
+            RequestBody::GroupAdd(group, name) => vec![&self.tag, "GroupAdd", group, name],
+            RequestBody::GroupRemove(group, name) => vec![&self.tag, "GroupRemove", group, name],
+            RequestBody::GroupDestroy(group) => vec![&self.tag, "GroupDestroy", group],
+            RequestBody::GroupRole(group, name, role) => {
+                arg = role.to_string();
+                vec![&self.tag, "GroupRole", group, name, &arg]
+            }
+            RequestBody::Who(group) => match group {
+                Some(group) => vec![&self.tag, "Who", group],
+                None => vec![&self.tag, "Who"],
+            },
+            RequestBody::Whois(name) => vec![&self.tag, "Whois", name],
+            RequestBody::Presence(value) => {
+                arg = value.to_string();
+                vec![&self.tag, "Presence", &arg]
+            }
+            RequestBody::Subscribe(name) => vec![&self.tag, "Subscribe", name],
+            RequestBody::Unsubscribe(name) => vec![&self.tag, "Unsubscribe", name],
+            RequestBody::Ack(id) => {
+                arg = id.to_string();
+                vec![&self.tag, "Ack", &arg]
+            }
+            RequestBody::History(target, before, limit) => {
+                let mut options = Vec::new();
+                if let Some(before) = before {
+                    options.extend(["BEFORE".to_string(), before.to_string()]);
+                }
+                if let Some(limit) = limit {
+                    options.extend(["LIMIT".to_string(), limit.to_string()]);
+                }
+                args = options;
+
+                let mut tokens = vec![self.tag.as_str(), "History", target];
+                tokens.extend(args.iter().map(String::as_str));
+                tokens
+            }
+            RequestBody::Cap(caps) => {
+                args = caps.iter().map(|cap| cap.to_string()).collect();
+
+                let mut tokens = vec![self.tag.as_str(), "Cap"];
+                tokens.extend(args.iter().map(String::as_str));
+                tokens
+            }
+            RequestBody::Pong(token) => vec![&self.tag, "Pong", token],
+            RequestBody::Block(name, policy) => {
+                arg = policy.to_string();
+                vec![&self.tag, "Block", name, &arg]
+            }
+            RequestBody::Unblock(name) => vec![&self.tag, "Unblock", name],
+            RequestBody::Blocks => vec![&self.tag, "Blocks"],
+            RequestBody::Kick(name, reason) => match reason {
+                Some(reason) => vec![&self.tag, "Kick", name, reason],
+                None => vec![&self.tag, "Kick", name],
+            },
+            RequestBody::Ban(name, duration) => match duration {
+                Some(duration) => {
+                    arg = duration.to_string();
+                    vec![&self.tag, "Ban", name, &arg]
+                }
+                None => vec![&self.tag, "Ban", name],
+            },
+            RequestBody::Unban(name) => vec![&self.tag, "Unban", name],
+            RequestBody::Mute(name, duration) => match duration {
+                Some(duration) => {
+                    arg = duration.to_string();
+                    vec![&self.tag, "Mute", name, &arg]
+                }
+                None => vec![&self.tag, "Mute", name],
+            },
+            RequestBody::Unmute(name) => vec![&self.tag, "Unmute", name],
+            RequestBody::Announce(msg) => vec![&self.tag, "Announce", msg],
         };
 
         f.write_str(detokenize(&tokens).as_str())
@@ -101,9 +429,40 @@ mod tests {
         assert_eq!(request.tag, "A000");
 
         match request.body {
-            RequestBody::Send(name, msg) => {
+            RequestBody::Send(name, msg, online) => {
                 assert_eq!(name, "Dessera");
                 assert_eq!(msg, "How are you today?");
+                assert!(!online);
+            }
+            _ => panic!("Mismatched command, need Send but others found"),
+        }
+    }
+
+    #[test]
+    fn test_request_send_online() {
+        let request = Request::try_from("A000 Send Dessera Hello ONLINE").unwrap();
+
+        match request.body {
+            RequestBody::Send(name, msg, online) => {
+                assert_eq!(name, "Dessera");
+                assert_eq!(msg, "Hello");
+                assert!(online);
+            }
+            _ => panic!("Mismatched command, need Send but others found"),
+        }
+
+        assert!(Request::try_from("A000 Send Dessera Hello LATER").is_err());
+    }
+
+    #[test]
+    fn test_request_send_group() {
+        let request = Request::try_from("A000 Send G:Team Hello").unwrap();
+        assert_eq!(request.tag, "A000");
+
+        match request.body {
+            RequestBody::Send(name, msg, _) => {
+                assert_eq!(name.strip_prefix(GROUP_PREFIX), Some("Team"));
+                assert_eq!(msg, "Hello");
             }
             _ => panic!("Mismatched command, need Send but others found"),
         }
@@ -123,6 +482,47 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_request_resume() {
+        let request = Request::try_from("A000 Resume 0123456789abcdef").unwrap();
+        assert_eq!(request.tag, "A000");
+
+        match request.body {
+            RequestBody::Resume(token) => assert_eq!(token, "0123456789abcdef"),
+            _ => panic!("Mismatched command, need Resume but others found"),
+        }
+
+        assert!(Request::try_from("A001 Resume").is_err());
+    }
+
+    #[test]
+    fn test_request_register() {
+        let request = Request::try_from("A000 Register Dessera Password").unwrap();
+        assert_eq!(request.tag, "A000");
+
+        match request.body {
+            RequestBody::Register(name, password) => {
+                assert_eq!(name, "Dessera");
+                assert_eq!(password, "Password");
+            }
+            _ => panic!("Mismatched command, need Register but others found"),
+        }
+    }
+
+    #[test]
+    fn test_request_passwd() {
+        let request = Request::try_from("A000 Passwd Pass \"New Password\"").unwrap();
+        assert_eq!(request.tag, "A000");
+
+        match request.body {
+            RequestBody::Passwd(old, new) => {
+                assert_eq!(old, "Pass");
+                assert_eq!(new, "New Password");
+            }
+            _ => panic!("Mismatched command, need Passwd but others found"),
+        }
+    }
+
     #[test]
     fn test_request_logout() {
         let request = Request::try_from("A000 Logout").unwrap();
@@ -145,6 +545,240 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_request_group() {
+        let request = Request::try_from("A000 GroupCreate Team").unwrap();
+        match request.body {
+            RequestBody::GroupCreate(group) => assert_eq!(group, "Team"),
+            _ => panic!("Mismatched command, need GroupCreate but others found"),
+        }
+
+        let request = Request::try_from("A001 GroupAdd Team Scarlet").unwrap();
+        match request.body {
+            RequestBody::GroupAdd(group, name) => {
+                assert_eq!(group, "Team");
+                assert_eq!(name, "Scarlet");
+            }
+            _ => panic!("Mismatched command, need GroupAdd but others found"),
+        }
+
+        let request = Request::try_from("A002 GroupRemove Team Scarlet").unwrap();
+        match request.body {
+            RequestBody::GroupRemove(group, name) => {
+                assert_eq!(group, "Team");
+                assert_eq!(name, "Scarlet");
+            }
+            _ => panic!("Mismatched command, need GroupRemove but others found"),
+        }
+
+        let request = Request::try_from("A003 GroupDestroy Team").unwrap();
+        match request.body {
+            RequestBody::GroupDestroy(group) => assert_eq!(group, "Team"),
+            _ => panic!("Mismatched command, need GroupDestroy but others found"),
+        }
+
+        assert!(Request::try_from("A004 GroupAdd Team").is_err());
+    }
+
+    #[test]
+    fn test_request_who() {
+        let request = Request::try_from("A000 Who").unwrap();
+        match request.body {
+            RequestBody::Who(None) => (),
+            _ => panic!("Mismatched command, need Who but others found"),
+        }
+
+        let request = Request::try_from("A001 Who Team").unwrap();
+        match request.body {
+            RequestBody::Who(Some(group)) => assert_eq!(group, "Team"),
+            _ => panic!("Mismatched command, need Who but others found"),
+        }
+
+        let request = Request::try_from("A002 Whois Scarlet").unwrap();
+        match request.body {
+            RequestBody::Whois(name) => assert_eq!(name, "Scarlet"),
+            _ => panic!("Mismatched command, need Whois but others found"),
+        }
+
+        assert!(Request::try_from("A003 Whois").is_err());
+    }
+
+    #[test]
+    fn test_request_presence() {
+        let request = Request::try_from("A000 Presence Away").unwrap();
+        match request.body {
+            RequestBody::Presence(presence) => assert_eq!(presence, Presence::Away),
+            _ => panic!("Mismatched command, need Presence but others found"),
+        }
+
+        let request = Request::try_from("A001 Subscribe Scarlet").unwrap();
+        match request.body {
+            RequestBody::Subscribe(name) => assert_eq!(name, "Scarlet"),
+            _ => panic!("Mismatched command, need Subscribe but others found"),
+        }
+
+        let request = Request::try_from("A002 Unsubscribe Scarlet").unwrap();
+        match request.body {
+            RequestBody::Unsubscribe(name) => assert_eq!(name, "Scarlet"),
+            _ => panic!("Mismatched command, need Unsubscribe but others found"),
+        }
+
+        assert!(Request::try_from("A003 Presence Busy").is_err());
+    }
+
+    #[test]
+    fn test_request_ack() {
+        let request = Request::try_from("A000 Ack 42").unwrap();
+        match request.body {
+            RequestBody::Ack(id) => assert_eq!(id, 42),
+            _ => panic!("Mismatched command, need Ack but others found"),
+        }
+
+        assert!(Request::try_from("A001 Ack").is_err());
+        assert!(Request::try_from("A002 Ack Forty").is_err());
+    }
+
+    #[test]
+    fn test_request_history() {
+        let request = Request::try_from("A000 History G:Team").unwrap();
+        match request.body {
+            RequestBody::History(target, before, limit) => {
+                assert_eq!(target, "G:Team");
+                assert_eq!(before, None);
+                assert_eq!(limit, None);
+            }
+            _ => panic!("Mismatched command, need History but others found"),
+        }
+
+        let request = Request::try_from("A001 History Dessera LIMIT 10 BEFORE 42").unwrap();
+        match request.body {
+            RequestBody::History(target, before, limit) => {
+                assert_eq!(target, "Dessera");
+                assert_eq!(before, Some(42));
+                assert_eq!(limit, Some(10));
+            }
+            _ => panic!("Mismatched command, need History but others found"),
+        }
+
+        assert!(Request::try_from("A002 History").is_err());
+        assert!(Request::try_from("A003 History Dessera BEFORE").is_err());
+        assert!(Request::try_from("A004 History Dessera LIMIT Ten").is_err());
+        assert!(Request::try_from("A005 History Dessera AFTER 42").is_err());
+    }
+
+    #[test]
+    fn test_request_cap() {
+        let request = Request::try_from("A000 Cap ids ack zstd").unwrap();
+        match request.body {
+            RequestBody::Cap(caps) => assert_eq!(caps, vec![Capability::Ids, Capability::Ack]),
+            _ => panic!("Mismatched command, need Cap but others found"),
+        }
+
+        let request = Request::try_from("A001 Cap").unwrap();
+        assert!(matches!(request.body, RequestBody::Cap(caps) if caps.is_empty()));
+    }
+
+    #[test]
+    fn test_request_pong() {
+        let request = Request::try_from("A000 Pong 0a1b").unwrap();
+        match request.body {
+            RequestBody::Pong(token) => assert_eq!(token, "0a1b"),
+            _ => panic!("Mismatched command, need Pong but others found"),
+        }
+
+        assert!(Request::try_from("A001 Pong").is_err());
+    }
+
+    #[test]
+    fn test_request_group_role() {
+        let request = Request::try_from("A000 GroupRole Team Scarlet Moderator").unwrap();
+        match request.body {
+            RequestBody::GroupRole(group, name, role) => {
+                assert_eq!(group, "Team");
+                assert_eq!(name, "Scarlet");
+                assert_eq!(role, GroupRole::Moderator);
+            }
+            _ => panic!("Mismatched command, need GroupRole but others found"),
+        }
+
+        assert!(Request::try_from("A001 GroupRole Team Scarlet").is_err());
+        assert!(Request::try_from("A002 GroupRole Team Scarlet Admin").is_err());
+    }
+
+    #[test]
+    fn test_request_moderation() {
+        let request = Request::try_from("A000 Kick Dessera \"Too noisy\"").unwrap();
+        match request.body {
+            RequestBody::Kick(name, reason) => {
+                assert_eq!(name, "Dessera");
+                assert_eq!(reason.as_deref(), Some("Too noisy"));
+            }
+            _ => panic!("Mismatched command, need Kick but others found"),
+        }
+
+        let request = Request::try_from("A001 Ban Dessera 3600").unwrap();
+        match request.body {
+            RequestBody::Ban(name, duration) => {
+                assert_eq!(name, "Dessera");
+                assert_eq!(duration, Some(3600));
+            }
+            _ => panic!("Mismatched command, need Ban but others found"),
+        }
+
+        let request = Request::try_from("A002 Mute Dessera").unwrap();
+        assert!(matches!(request.body, RequestBody::Mute(_, None)));
+
+        let request = Request::try_from("A003 Unban Dessera").unwrap();
+        assert!(matches!(request.body, RequestBody::Unban(_)));
+
+        let request = Request::try_from("A004 Unmute Dessera").unwrap();
+        assert!(matches!(request.body, RequestBody::Unmute(_)));
+
+        assert!(Request::try_from("A005 Kick").is_err());
+        assert!(Request::try_from("A006 Ban Dessera Forever").is_err());
+    }
+
+    #[test]
+    fn test_request_announce() {
+        let request = Request::try_from("A000 Announce \"Server restarts soon\"").unwrap();
+        match request.body {
+            RequestBody::Announce(msg) => assert_eq!(msg, "Server restarts soon"),
+            _ => panic!("Mismatched command, need Announce but others found"),
+        }
+
+        assert!(Request::try_from("A001 Announce").is_err());
+    }
+
+    #[test]
+    fn test_request_block() {
+        let request = Request::try_from("A000 Block Dessera").unwrap();
+        match request.body {
+            RequestBody::Block(name, policy) => {
+                assert_eq!(name, "Dessera");
+                assert_eq!(policy, BlockPolicy::Silent);
+            }
+            _ => panic!("Mismatched command, need Block but others found"),
+        }
+
+        let request = Request::try_from("A001 Block Dessera Reject").unwrap();
+        match request.body {
+            RequestBody::Block(_, policy) => assert_eq!(policy, BlockPolicy::Reject),
+            _ => panic!("Mismatched command, need Block but others found"),
+        }
+
+        let request = Request::try_from("A002 Unblock Dessera").unwrap();
+        match request.body {
+            RequestBody::Unblock(name) => assert_eq!(name, "Dessera"),
+            _ => panic!("Mismatched command, need Unblock but others found"),
+        }
+
+        let request = Request::try_from("A003 Blocks").unwrap();
+        assert!(matches!(request.body, RequestBody::Blocks));
+
+        assert!(Request::try_from("A004 Block").is_err());
+        assert!(Request::try_from("A005 Block Dessera Ignore").is_err());
+    }
+
     #[test]
     fn test_request_failed() {
         let request = Request::try_from("A000 Invalid Command");
@@ -155,12 +789,22 @@ mod tests {
     fn test_request_display_send() {
         let request = Request::new(
             "A000",
-            RequestBody::Send("Dessera".to_string(), "Hello! How are you?".to_string()),
+            RequestBody::Send(
+                "Dessera".to_string(),
+                "Hello! How are you?".to_string(),
+                false,
+            ),
         );
         assert_eq!(
             request.to_string(),
             "A000 Send Dessera \"Hello! How are you?\""
         );
+
+        let request = Request::new(
+            "A001",
+            RequestBody::Send("G:Team".to_string(), "Hello".to_string(), true),
+        );
+        assert_eq!(request.to_string(), "A001 Send G:Team Hello ONLINE");
     }
 
     #[test]
@@ -172,6 +816,30 @@ mod tests {
         assert_eq!(request.to_string(), "A000 Login Dessera Pass");
     }
 
+    #[test]
+    fn test_request_display_resume() {
+        let request = Request::new("A000", RequestBody::Resume("0123456789abcdef".to_string()));
+        assert_eq!(request.to_string(), "A000 Resume 0123456789abcdef");
+    }
+
+    #[test]
+    fn test_request_display_register() {
+        let request = Request::new(
+            "A000",
+            RequestBody::Register("Dessera".to_string(), "Password".to_string()),
+        );
+        assert_eq!(request.to_string(), "A000 Register Dessera Password");
+    }
+
+    #[test]
+    fn test_request_display_passwd() {
+        let request = Request::new(
+            "A000",
+            RequestBody::Passwd("Pass".to_string(), "New Password".to_string()),
+        );
+        assert_eq!(request.to_string(), "A000 Passwd Pass \"New Password\"");
+    }
+
     #[test]
     fn test_request_display_logout() {
         let request = Request::new("A000", RequestBody::Logout);
@@ -183,4 +851,130 @@ mod tests {
         let request = Request::new("A000", RequestBody::Nop);
         assert_eq!(request.to_string(), "A000 Nop");
     }
+
+    #[test]
+    fn test_request_display_group() {
+        let request = Request::new("A000", RequestBody::GroupCreate("Team".to_string()));
+        assert_eq!(request.to_string(), "A000 GroupCreate Team");
+
+        let request = Request::new(
+            "A000",
+            RequestBody::GroupAdd("Team".to_string(), "Scarlet".to_string()),
+        );
+        assert_eq!(request.to_string(), "A000 GroupAdd Team Scarlet");
+
+        let request = Request::new(
+            "A000",
+            RequestBody::GroupRemove("Team".to_string(), "Scarlet".to_string()),
+        );
+        assert_eq!(request.to_string(), "A000 GroupRemove Team Scarlet");
+
+        let request = Request::new("A000", RequestBody::GroupDestroy("Team".to_string()));
+        assert_eq!(request.to_string(), "A000 GroupDestroy Team");
+    }
+
+    #[test]
+    fn test_request_display_who() {
+        let request = Request::new("A000", RequestBody::Who(None));
+        assert_eq!(request.to_string(), "A000 Who");
+
+        let request = Request::new("A000", RequestBody::Who(Some("Team".to_string())));
+        assert_eq!(request.to_string(), "A000 Who Team");
+
+        let request = Request::new("A000", RequestBody::Whois("Scarlet".to_string()));
+        assert_eq!(request.to_string(), "A000 Whois Scarlet");
+    }
+
+    #[test]
+    fn test_request_display_presence() {
+        let request = Request::new("A000", RequestBody::Presence(Presence::Online));
+        assert_eq!(request.to_string(), "A000 Presence Online");
+
+        let request = Request::new("A000", RequestBody::Subscribe("Scarlet".to_string()));
+        assert_eq!(request.to_string(), "A000 Subscribe Scarlet");
+
+        let request = Request::new("A000", RequestBody::Unsubscribe("Scarlet".to_string()));
+        assert_eq!(request.to_string(), "A000 Unsubscribe Scarlet");
+    }
+
+    #[test]
+    fn test_request_display_ack() {
+        let request = Request::new("A000", RequestBody::Ack(42));
+        assert_eq!(request.to_string(), "A000 Ack 42");
+    }
+
+    #[test]
+    fn test_request_display_history() {
+        let request = Request::new("A000", RequestBody::History("G:Team".into(), None, None));
+        assert_eq!(request.to_string(), "A000 History G:Team");
+
+        let request = Request::new(
+            "A001",
+            RequestBody::History("Dessera".into(), Some(42), Some(10)),
+        );
+        assert_eq!(
+            request.to_string(),
+            "A001 History Dessera BEFORE 42 LIMIT 10"
+        );
+    }
+
+    #[test]
+    fn test_request_display_cap() {
+        let request = Request::new(
+            "A000",
+            RequestBody::Cap(vec![Capability::Ack, Capability::Echo]),
+        );
+        assert_eq!(request.to_string(), "A000 Cap ack echo");
+    }
+
+    #[test]
+    fn test_request_display_pong() {
+        let request = Request::new("A000", RequestBody::Pong("0a1b".into()));
+        assert_eq!(request.to_string(), "A000 Pong 0a1b");
+    }
+
+    #[test]
+    fn test_request_display_group_role() {
+        let request = Request::new(
+            "A000",
+            RequestBody::GroupRole("Team".into(), "Scarlet".into(), GroupRole::Owner),
+        );
+        assert_eq!(request.to_string(), "A000 GroupRole Team Scarlet Owner");
+    }
+
+    #[test]
+    fn test_request_display_moderation() {
+        let request = Request::new(
+            "A000",
+            RequestBody::Kick("Dessera".into(), Some("Too noisy".into())),
+        );
+        assert_eq!(request.to_string(), "A000 Kick Dessera \"Too noisy\"");
+
+        let request = Request::new("A001", RequestBody::Ban("Dessera".into(), Some(60)));
+        assert_eq!(request.to_string(), "A001 Ban Dessera 60");
+
+        let request = Request::new("A002", RequestBody::Mute("Dessera".into(), None));
+        assert_eq!(request.to_string(), "A002 Mute Dessera");
+
+        let request = Request::new("A003", RequestBody::Unmute("Dessera".into()));
+        assert_eq!(request.to_string(), "A003 Unmute Dessera");
+
+        let request = Request::new("A004", RequestBody::Announce("Hello all".into()));
+        assert_eq!(request.to_string(), "A004 Announce \"Hello all\"");
+    }
+
+    #[test]
+    fn test_request_display_block() {
+        let request = Request::new(
+            "A000",
+            RequestBody::Block("Dessera".into(), BlockPolicy::Reject),
+        );
+        assert_eq!(request.to_string(), "A000 Block Dessera Reject");
+
+        let request = Request::new("A001", RequestBody::Unblock("Dessera".into()));
+        assert_eq!(request.to_string(), "A001 Unblock Dessera");
+
+        let request = Request::new("A002", RequestBody::Blocks);
+        assert_eq!(request.to_string(), "A002 Blocks");
+    }
 }
@@ -1,12 +1,17 @@
 use crate::{
     QuipError, QuipResult,
+    server::connection::unix_time,
     token::{detokenize, tokenize},
     unwrap_token,
 };
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
 /// Error type of response.
 ///
+/// `Unauthorized` means the connection is not logged in or the credentials
+/// are wrong, while `Forbidden` means the user lacks the role for a command.
+///
 /// All errors returned by server should have the following format:
 ///
 /// ```plaintext
@@ -19,13 +24,21 @@ use std::fmt;
 /// A000 Error BadCommand
 /// A001 Error Unauthorized
 /// ```
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ResponseError {
     BadCommand,
     Unauthorized,
     Authorized,
     Duplicate,
     NotFound,
+    WeakPassword,
+    RegisterDisabled,
+    TooLong,
+    RateLimited,
+    QueueFull,
+    Offline,
+    Blocked,
+    Forbidden,
 }
 
 impl TryFrom<String> for ResponseError {
@@ -46,6 +59,14 @@ impl TryFrom<&str> for ResponseError {
             "Authorized" => ResponseError::Authorized,
             "Duplicate" => ResponseError::Duplicate,
             "NotFound" => ResponseError::NotFound,
+            "WeakPassword" => ResponseError::WeakPassword,
+            "RegisterDisabled" => ResponseError::RegisterDisabled,
+            "TooLong" => ResponseError::TooLong,
+            "RateLimited" => ResponseError::RateLimited,
+            "QueueFull" => ResponseError::QueueFull,
+            "Offline" => ResponseError::Offline,
+            "Blocked" => ResponseError::Blocked,
+            "Forbidden" => ResponseError::Forbidden,
             _ => {
                 return Err(QuipError::Parse(format!(
                     "{} is not a valid ResponseError",
@@ -66,26 +87,525 @@ impl fmt::Display for ResponseError {
             ResponseError::Authorized => "Authorized",
             ResponseError::Duplicate => "Duplicate",
             ResponseError::NotFound => "NotFound",
+            ResponseError::WeakPassword => "WeakPassword",
+            ResponseError::RegisterDisabled => "RegisterDisabled",
+            ResponseError::TooLong => "TooLong",
+            ResponseError::RateLimited => "RateLimited",
+            ResponseError::QueueFull => "QueueFull",
+            ResponseError::Offline => "Offline",
+            ResponseError::Blocked => "Blocked",
+            ResponseError::Forbidden => "Forbidden",
+        })
+    }
+}
+
+/// Level of server notice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoticeLevel {
+    Info,
+    Warn,
+    Error,
+}
+
+impl TryFrom<String> for NoticeLevel {
+    type Error = QuipError;
+
+    fn try_from(value: String) -> QuipResult<Self> {
+        NoticeLevel::try_from(value.as_str())
+    }
+}
+
+impl TryFrom<&str> for NoticeLevel {
+    type Error = QuipError;
+
+    fn try_from(value: &str) -> QuipResult<Self> {
+        let level = match value {
+            "Info" => NoticeLevel::Info,
+            "Warn" => NoticeLevel::Warn,
+            "Error" => NoticeLevel::Error,
+            _ => {
+                return Err(QuipError::Parse(format!(
+                    "{} is not a valid NoticeLevel",
+                    value
+                )));
+            }
+        };
+
+        Ok(level)
+    }
+}
+
+impl fmt::Display for NoticeLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            NoticeLevel::Info => "Info",
+            NoticeLevel::Warn => "Warn",
+            NoticeLevel::Error => "Error",
+        })
+    }
+}
+
+/// Online state of a user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Presence {
+    Online,
+    Offline,
+    Away,
+}
+
+impl TryFrom<String> for Presence {
+    type Error = QuipError;
+
+    fn try_from(value: String) -> QuipResult<Self> {
+        Presence::try_from(value.as_str())
+    }
+}
+
+impl TryFrom<&str> for Presence {
+    type Error = QuipError;
+
+    fn try_from(value: &str) -> QuipResult<Self> {
+        let presence = match value {
+            "Online" => Presence::Online,
+            "Offline" => Presence::Offline,
+            "Away" => Presence::Away,
+            _ => {
+                return Err(QuipError::Parse(format!(
+                    "{} is not a valid Presence",
+                    value
+                )));
+            }
+        };
+
+        Ok(presence)
+    }
+}
+
+impl fmt::Display for Presence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Presence::Online => "Online",
+            Presence::Offline => "Offline",
+            Presence::Away => "Away",
+        })
+    }
+}
+
+/// Role of a user in a group.
+///
+/// - `Owner`: Manages the group, including roles and destroying it.
+/// - `Moderator`: Adds users to the group and removes members.
+/// - `Member`: Leaves the group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupRole {
+    Owner,
+    Moderator,
+    Member,
+}
+
+impl TryFrom<String> for GroupRole {
+    type Error = QuipError;
+
+    fn try_from(value: String) -> QuipResult<Self> {
+        GroupRole::try_from(value.as_str())
+    }
+}
+
+impl TryFrom<&str> for GroupRole {
+    type Error = QuipError;
+
+    fn try_from(value: &str) -> QuipResult<Self> {
+        let role = match value {
+            "Owner" => GroupRole::Owner,
+            "Moderator" => GroupRole::Moderator,
+            "Member" => GroupRole::Member,
+            _ => {
+                return Err(QuipError::Parse(format!(
+                    "{} is not a valid GroupRole",
+                    value
+                )));
+            }
+        };
+
+        Ok(role)
+    }
+}
+
+impl fmt::Display for GroupRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            GroupRole::Owner => "Owner",
+            GroupRole::Moderator => "Moderator",
+            GroupRole::Member => "Member",
+        })
+    }
+}
+
+/// How messages from a blocked user are handled, decided by the blocking
+/// user.
+///
+/// - `Silent`: Messages are dropped, the sender is not told.
+/// - `Reject`: Messages are rejected, the sender receives `Blocked`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BlockPolicy {
+    #[default]
+    Silent,
+    Reject,
+}
+
+impl TryFrom<String> for BlockPolicy {
+    type Error = QuipError;
+
+    fn try_from(value: String) -> QuipResult<Self> {
+        BlockPolicy::try_from(value.as_str())
+    }
+}
+
+impl TryFrom<&str> for BlockPolicy {
+    type Error = QuipError;
+
+    fn try_from(value: &str) -> QuipResult<Self> {
+        let policy = match value {
+            "Silent" => BlockPolicy::Silent,
+            "Reject" => BlockPolicy::Reject,
+            _ => {
+                return Err(QuipError::Parse(format!(
+                    "{} is not a valid BlockPolicy",
+                    value
+                )));
+            }
+        };
+
+        Ok(policy)
+    }
+}
+
+impl fmt::Display for BlockPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BlockPolicy::Silent => "Silent",
+            BlockPolicy::Reject => "Reject",
+        })
+    }
+}
+
+/// Version of the protocol announced in `Hello` response.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Protocol extension negotiated with `Cap` request.
+///
+/// Clients without a capability are served as legacy clients:
+///
+/// - `ids`: `Recv` responses carry message IDs.
+/// - `ack`: Messages are delivered again after `Resume` until acknowledged,
+///   which implies `ids`. At most 1000 unacknowledged messages are kept in a
+///   session, the oldest one is dropped beyond it.
+/// - `presence`: `Presence` responses are pushed.
+/// - `echo`: `Sent` responses are pushed.
+/// - `resume`: `Login` and `Resume` return a token to resume the session, i.e.
+///   `<NAME> <TOKEN>` instead of `<NAME>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Capability {
+    Ids,
+    Ack,
+    Presence,
+    Echo,
+    Resume,
+}
+
+impl Capability {
+    /// All capabilities supported by current version.
+    pub const ALL: [Capability; 5] = [
+        Capability::Ids,
+        Capability::Ack,
+        Capability::Presence,
+        Capability::Echo,
+        Capability::Resume,
+    ];
+
+    /// Parse known capabilities, unknown ones are skipped so that newer peers
+    /// can still be served.
+    pub fn parse_list(items: impl IntoIterator<Item = String>) -> Vec<Capability> {
+        items
+            .into_iter()
+            .filter_map(|item| Capability::try_from(item).ok())
+            .collect()
+    }
+}
+
+impl TryFrom<String> for Capability {
+    type Error = QuipError;
+
+    fn try_from(value: String) -> QuipResult<Self> {
+        Capability::try_from(value.as_str())
+    }
+}
+
+impl TryFrom<&str> for Capability {
+    type Error = QuipError;
+
+    fn try_from(value: &str) -> QuipResult<Self> {
+        let cap = match value {
+            "ids" => Capability::Ids,
+            "ack" => Capability::Ack,
+            "presence" => Capability::Presence,
+            "echo" => Capability::Echo,
+            "resume" => Capability::Resume,
+            _ => {
+                return Err(QuipError::Parse(format!(
+                    "{} is not a valid Capability",
+                    value
+                )));
+            }
+        };
+
+        Ok(cap)
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Capability::Ids => "ids",
+            Capability::Ack => "ack",
+            Capability::Presence => "presence",
+            Capability::Echo => "echo",
+            Capability::Resume => "resume",
+        })
+    }
+}
+
+/// User information in the payload of `Whois` response, i.e.
+/// `<NAME> <PRESENCE> <LAST SEEN> <GROUPS...>`.
+///
+/// The last seen time is in seconds since UNIX epoch, or `-` if unknown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserInfo {
+    pub name: String,
+    pub presence: Presence,
+    pub last_seen: Option<u64>,
+    pub groups: Vec<String>,
+}
+
+impl TryFrom<String> for UserInfo {
+    type Error = QuipError;
+
+    fn try_from(value: String) -> QuipResult<Self> {
+        UserInfo::try_from(value.as_str())
+    }
+}
+
+impl TryFrom<&str> for UserInfo {
+    type Error = QuipError;
+
+    fn try_from(value: &str) -> QuipResult<Self> {
+        let mut tokens = tokenize(value)?.into_iter();
+
+        let name = unwrap_token!(tokens, "No name found for user info");
+        let presence = unwrap_token!(tokens, "No presence found for user info");
+        let last_seen = unwrap_token!(tokens, "No last seen found for user info");
+        let last_seen = match last_seen.as_str() {
+            "-" => None,
+            _ => Some(last_seen.parse().map_err(|_| {
+                QuipError::Parse(format!("{} is not a valid timestamp", last_seen))
+            })?),
+        };
+
+        Ok(UserInfo {
+            name,
+            presence: presence.try_into()?,
+            last_seen,
+            groups: tokens.collect(),
+        })
+    }
+}
+
+impl fmt::Display for UserInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let last_seen = match self.last_seen {
+            Some(time) => time.to_string(),
+            None => "-".into(),
+        };
+
+        let mut tokens = vec![self.name.clone(), self.presence.to_string(), last_seen];
+        tokens.extend(self.groups.iter().cloned());
+
+        f.write_str(detokenize(&tokens).as_str())
+    }
+}
+
+/// Message in the payload of `History` response, i.e.
+/// `<ID> <TIME> <SENDER> <TARGET> <MSG>`.
+///
+/// The target is a user or `G:<GROUP>`, and the time is in seconds since UNIX
+/// epoch. Messages are encoded as a list, see [`encode_list`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub id: u64,
+    pub time: u64,
+    pub sender: String,
+    pub target: String,
+    pub msg: String,
+}
+
+impl HistoryEntry {
+    /// Create a message sent just now.
+    pub fn new(
+        id: u64,
+        sender: impl Into<String>,
+        target: impl Into<String>,
+        msg: impl Into<String>,
+    ) -> Self {
+        Self {
+            id,
+            time: unix_time(),
+            sender: sender.into(),
+            target: target.into(),
+            msg: msg.into(),
+        }
+    }
+}
+
+impl TryFrom<String> for HistoryEntry {
+    type Error = QuipError;
+
+    fn try_from(value: String) -> QuipResult<Self> {
+        HistoryEntry::try_from(value.as_str())
+    }
+}
+
+impl TryFrom<&str> for HistoryEntry {
+    type Error = QuipError;
+
+    fn try_from(value: &str) -> QuipResult<Self> {
+        let mut tokens = tokenize(value)?.into_iter();
+
+        let id = unwrap_token!(tokens, "No ID found for history");
+        let id = id
+            .parse()
+            .map_err(|_| QuipError::Parse(format!("{} is not a valid message ID", id)))?;
+        let time = unwrap_token!(tokens, "No time found for history");
+        let time = time
+            .parse()
+            .map_err(|_| QuipError::Parse(format!("{} is not a valid timestamp", time)))?;
+
+        Ok(HistoryEntry {
+            id,
+            time,
+            sender: unwrap_token!(tokens, "No sender found for history"),
+            target: unwrap_token!(tokens, "No target found for history"),
+            msg: unwrap_token!(tokens, "No message found for history"),
         })
     }
 }
 
+impl fmt::Display for HistoryEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tokens = vec![
+            self.id.to_string(),
+            self.time.to_string(),
+            self.sender.clone(),
+            self.target.clone(),
+            self.msg.clone(),
+        ];
+
+        f.write_str(detokenize(&tokens).as_str())
+    }
+}
+
+/// Blocked user in the payload of `Blocks` response, i.e. `<NAME> <POLICY>`.
+///
+/// Blocked users are encoded as a list, see [`encode_list`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockEntry {
+    pub name: String,
+    pub policy: BlockPolicy,
+}
+
+impl TryFrom<String> for BlockEntry {
+    type Error = QuipError;
+
+    fn try_from(value: String) -> QuipResult<Self> {
+        BlockEntry::try_from(value.as_str())
+    }
+}
+
+impl TryFrom<&str> for BlockEntry {
+    type Error = QuipError;
+
+    fn try_from(value: &str) -> QuipResult<Self> {
+        let mut tokens = tokenize(value)?.into_iter();
+
+        let name = unwrap_token!(tokens, "No name found for block");
+        let policy = unwrap_token!(tokens, "No policy found for block");
+
+        Ok(BlockEntry {
+            name,
+            policy: policy.try_into()?,
+        })
+    }
+}
+
+impl fmt::Display for BlockEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tokens = vec![self.name.clone(), self.policy.to_string()];
+
+        f.write_str(detokenize(&tokens).as_str())
+    }
+}
+
+/// Encode a list as the payload of `Success` response, `None` if empty.
+pub fn encode_list(items: &[impl AsRef<str>]) -> Option<String> {
+    match items.is_empty() {
+        true => None,
+        false => Some(detokenize(&items.iter().collect())),
+    }
+}
+
+/// Decode a list from the payload of `Success` response.
+pub fn decode_list(payload: Option<&str>) -> QuipResult<Vec<String>> {
+    match payload {
+        Some(payload) => tokenize(payload),
+        None => Ok(Vec::new()),
+    }
+}
+
 /// General response body.
 ///
 /// A general response body may be one of the following responses:
 ///
 /// - `Success`: Command was processed successfully, i.e. `<TAG> Success <OPTIONAL STRING>`.
+///   Structured payload is encoded as a single token, see [`encode_list`],
+///   [`UserInfo`], [`HistoryEntry`] and [`BlockEntry`].
 /// - `Error`: Error occurred when peocessing command, i.e. `<TAG> Error <CODE>`.
-/// - `Recv`: Received command from other users, i.e. `* Recv (<GROUP>:)<USER> <MSG>`.
-#[derive(Debug)]
+/// - `Recv`: Received command from other users, i.e.
+///   `* Recv (<GROUP>:)<USER> <MSG> <ID>`, the message should be acknowledged
+///   with `Ack <ID>`. Legacy messages have no ID.
+/// - `Notice`: Message from server, i.e. `* Notice <LEVEL> <MSG>`.
+/// - `Presence`: Online state of another user changed, i.e.
+///   `* Presence <USER> <PRESENCE>`.
+/// - `Sent`: Message sent by current user from another session, i.e.
+///   `* Sent <USER> <MSG>` or `* Sent G:<GROUP> <MSG>`.
+/// - `Hello`: Greeting sent when connected, i.e.
+///   `* Hello <VERSION> <MAX LINE> <CAPABILITIES...>`, see [`Capability`].
+///   Requests longer than `MAX LINE` bytes are discarded with `TooLong`, `0`
+///   if unlimited.
+/// - `Ping`: Keepalive check, i.e. `* Ping <TOKEN>`, which should be answered
+///   with `Pong <TOKEN>` before the connection is closed.
+#[derive(Debug, Clone)]
 pub enum ResponseBody {
     Success(Option<String>),
     Error(ResponseError),
-    Recv(String, String),
+    Recv(String, String, Option<u64>),
+    Notice(NoticeLevel, String),
+    Presence(String, Presence),
+    Sent(String, String),
+    Hello(u32, usize, Vec<Capability>),
+    Ping(String),
 }
 
 /// General response, with optional request info.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Response {
     pub tag: Option<String>,
     pub body: ResponseBody,
@@ -105,7 +625,48 @@ impl Response {
     }
 
     pub fn recv(tag: Option<String>, sender: impl Into<String>, msg: impl Into<String>) -> Self {
-        Response::new(tag, ResponseBody::Recv(sender.into(), msg.into()))
+        Response::new(tag, ResponseBody::Recv(sender.into(), msg.into(), None))
+    }
+
+    /// Create an untagged `Recv` response with message ID.
+    pub fn message(id: u64, sender: impl Into<String>, msg: impl Into<String>) -> Self {
+        Response::new(
+            None,
+            ResponseBody::Recv(sender.into(), msg.into(), Some(id)),
+        )
+    }
+
+    pub fn notice(level: NoticeLevel, msg: impl Into<String>) -> Self {
+        Response::new(None, ResponseBody::Notice(level, msg.into()))
+    }
+
+    /// Message ID of `Recv` response.
+    pub fn message_id(&self) -> Option<u64> {
+        match &self.body {
+            ResponseBody::Recv(_, _, id) => *id,
+            _ => None,
+        }
+    }
+
+    pub fn presence(name: impl Into<String>, presence: Presence) -> Self {
+        Response::new(None, ResponseBody::Presence(name.into(), presence))
+    }
+
+    pub fn sent(receiver: impl Into<String>, msg: impl Into<String>) -> Self {
+        Response::new(None, ResponseBody::Sent(receiver.into(), msg.into()))
+    }
+
+    pub fn ping(token: impl Into<String>) -> Self {
+        Response::new(None, ResponseBody::Ping(token.into()))
+    }
+
+    /// Create the greeting of current protocol version, with maximum length
+    /// of request lines.
+    pub fn hello(max_line: usize) -> Self {
+        Response::new(
+            None,
+            ResponseBody::Hello(PROTOCOL_VERSION, max_line, Capability::ALL.to_vec()),
+        )
     }
 }
 
@@ -139,8 +700,48 @@ impl TryFrom<&str> for Response {
             "Recv" => {
                 let name = unwrap_token!(tokens, "No name found for response Recv");
                 let msg = unwrap_token!(tokens, "No message found for response Recv");
+                let id = match tokens.next() {
+                    Some(id) => Some(id.parse().map_err(|_| {
+                        QuipError::Parse(format!("{} is not a valid message ID", id))
+                    })?),
+                    None => None,
+                };
+
+                ResponseBody::Recv(name, msg, id)
+            }
+            "Notice" => {
+                let level = unwrap_token!(tokens, "No level found for response Notice");
+                let msg = unwrap_token!(tokens, "No message found for response Notice");
+
+                ResponseBody::Notice(level.try_into()?, msg)
+            }
+            "Presence" => {
+                let name = unwrap_token!(tokens, "No name found for response Presence");
+                let presence = unwrap_token!(tokens, "No presence found for response Presence");
+
+                ResponseBody::Presence(name, presence.try_into()?)
+            }
+            "Sent" => {
+                let name = unwrap_token!(tokens, "No name found for response Sent");
+                let msg = unwrap_token!(tokens, "No message found for response Sent");
 
-                ResponseBody::Recv(name, msg)
+                ResponseBody::Sent(name, msg)
+            }
+            "Hello" => {
+                let version = unwrap_token!(tokens, "No version found for response Hello");
+                let version = version
+                    .parse()
+                    .map_err(|_| QuipError::Parse(format!("{} is not a valid version", version)))?;
+                let max_line = unwrap_token!(tokens, "No max line found for response Hello");
+                let max_line = max_line.parse().map_err(|_| {
+                    QuipError::Parse(format!("{} is not a valid line length", max_line))
+                })?;
+
+                ResponseBody::Hello(version, max_line, Capability::parse_list(tokens))
+            }
+            "Ping" => {
+                let token = unwrap_token!(tokens, "No token found for response Ping");
+                ResponseBody::Ping(token)
             }
             _ => {
                 return Err(QuipError::Parse(format!(
@@ -162,6 +763,10 @@ impl fmt::Display for Response {
         };
 
         let err_msg;
+        let level;
+        let state;
+        let msg_id;
+        let hello: Vec<String>;
         let tokens = match &self.body {
             ResponseBody::Success(msg) => match msg {
                 Some(msg) => vec![tag, "Success", msg],
@@ -171,9 +776,34 @@ impl fmt::Display for Response {
                 err_msg = msg.to_string();
                 vec![tag, "Error", err_msg.as_str()]
             }
-            ResponseBody::Recv(name, msg) => {
-                vec![tag, "Recv", name, msg]
+            ResponseBody::Recv(name, msg, id) => {
+                let mut tokens = vec![tag, "Recv", name, msg];
+                if let Some(id) = id {
+                    msg_id = id.to_string();
+                    tokens.push(msg_id.as_str());
+                }
+                tokens
+            }
+            ResponseBody::Notice(lvl, msg) => {
+                level = lvl.to_string();
+                vec![tag, "Notice", level.as_str(), msg]
+            }
+            ResponseBody::Presence(name, presence) => {
+                state = presence.to_string();
+                vec![tag, "Presence", name, state.as_str()]
+            }
+            ResponseBody::Sent(name, msg) => vec![tag, "Sent", name, msg],
+            ResponseBody::Hello(version, max_line, caps) => {
+                hello = [version.to_string(), max_line.to_string()]
+                    .into_iter()
+                    .chain(caps.iter().map(|cap| cap.to_string()))
+                    .collect();
+
+                let mut tokens = vec![tag, "Hello"];
+                tokens.extend(hello.iter().map(String::as_str));
+                tokens
             }
+            ResponseBody::Ping(token) => vec![tag, "Ping", token],
         };
 
         f.write_str(detokenize(&tokens).as_str())
@@ -206,6 +836,38 @@ mod tests {
             ResponseError::try_from("NotFound").unwrap(),
             ResponseError::NotFound
         );
+        assert_eq!(
+            ResponseError::try_from("WeakPassword").unwrap(),
+            ResponseError::WeakPassword
+        );
+        assert_eq!(
+            ResponseError::try_from("RegisterDisabled").unwrap(),
+            ResponseError::RegisterDisabled
+        );
+        assert_eq!(
+            ResponseError::try_from("TooLong").unwrap(),
+            ResponseError::TooLong
+        );
+        assert_eq!(
+            ResponseError::try_from("RateLimited").unwrap(),
+            ResponseError::RateLimited
+        );
+        assert_eq!(
+            ResponseError::try_from("QueueFull").unwrap(),
+            ResponseError::QueueFull
+        );
+        assert_eq!(
+            ResponseError::try_from("Offline").unwrap(),
+            ResponseError::Offline
+        );
+        assert_eq!(
+            ResponseError::try_from("Blocked").unwrap(),
+            ResponseError::Blocked
+        );
+        assert_eq!(
+            ResponseError::try_from("Forbidden").unwrap(),
+            ResponseError::Forbidden
+        );
     }
 
     #[test]
@@ -244,12 +906,74 @@ mod tests {
         assert!(resp.tag.is_none());
 
         match resp.body {
-            ResponseBody::Recv(name, msg) => {
+            ResponseBody::Recv(name, msg, id) => {
                 assert_eq!(name, "Dessera");
                 assert_eq!(msg, "How are you today?");
+                assert_eq!(id, None);
+            }
+            _ => panic!("Mismatched response, need Recv but others found"),
+        }
+
+        let resp = Response::try_from("* Recv Team:Dessera Hello 42").unwrap();
+        match resp.body {
+            ResponseBody::Recv(name, msg, id) => {
+                assert_eq!(name, "Team:Dessera");
+                assert_eq!(msg, "Hello");
+                assert_eq!(id, Some(42));
             }
             _ => panic!("Mismatched response, need Recv but others found"),
         }
+
+        assert!(Response::try_from("* Recv Dessera Hello Forty").is_err());
+    }
+
+    #[test]
+    fn test_response_notice() {
+        let resp = Response::try_from("* Notice Warn \"Server is shutting down\"").unwrap();
+        assert!(resp.tag.is_none());
+
+        match resp.body {
+            ResponseBody::Notice(level, msg) => {
+                assert_eq!(level, NoticeLevel::Warn);
+                assert_eq!(msg, "Server is shutting down");
+            }
+            _ => panic!("Mismatched response, need Notice but others found"),
+        }
+
+        assert!(Response::try_from("* Notice Invalid Message").is_err());
+    }
+
+    #[test]
+    fn test_response_presence() {
+        let resp = Response::try_from("* Presence Scarlet Away").unwrap();
+        assert!(resp.tag.is_none());
+
+        match resp.body {
+            ResponseBody::Presence(name, presence) => {
+                assert_eq!(name, "Scarlet");
+                assert_eq!(presence, Presence::Away);
+            }
+            _ => panic!("Mismatched response, need Presence but others found"),
+        }
+
+        assert!(Response::try_from("* Presence Scarlet").is_err());
+        assert!(Response::try_from("* Presence Scarlet Busy").is_err());
+    }
+
+    #[test]
+    fn test_response_sent() {
+        let resp = Response::try_from("* Sent G:Team \"See you tomorrow\"").unwrap();
+        assert!(resp.tag.is_none());
+
+        match resp.body {
+            ResponseBody::Sent(name, msg) => {
+                assert_eq!(name, "G:Team");
+                assert_eq!(msg, "See you tomorrow");
+            }
+            _ => panic!("Mismatched response, need Sent but others found"),
+        }
+
+        assert!(Response::try_from("* Sent Scarlet").is_err());
     }
 
     #[test]
@@ -259,6 +983,17 @@ mod tests {
         assert_eq!(ResponseError::Authorized.to_string(), "Authorized");
         assert_eq!(ResponseError::Duplicate.to_string(), "Duplicate");
         assert_eq!(ResponseError::NotFound.to_string(), "NotFound");
+        assert_eq!(ResponseError::WeakPassword.to_string(), "WeakPassword");
+        assert_eq!(
+            ResponseError::RegisterDisabled.to_string(),
+            "RegisterDisabled"
+        );
+        assert_eq!(ResponseError::TooLong.to_string(), "TooLong");
+        assert_eq!(ResponseError::RateLimited.to_string(), "RateLimited");
+        assert_eq!(ResponseError::QueueFull.to_string(), "QueueFull");
+        assert_eq!(ResponseError::Offline.to_string(), "Offline");
+        assert_eq!(ResponseError::Blocked.to_string(), "Blocked");
+        assert_eq!(ResponseError::Forbidden.to_string(), "Forbidden");
     }
 
     #[test]
@@ -292,5 +1027,187 @@ mod tests {
 
         let res = Response::recv(None, "Sender", "Complex  Message");
         assert_eq!(res.to_string(), "* Recv Sender \"Complex  Message\"");
+
+        let res = Response::message(42, "Sender", "Message");
+        assert_eq!(res.to_string(), "* Recv Sender Message 42");
+    }
+
+    #[test]
+    fn test_response_display_notice() {
+        let res = Response::notice(NoticeLevel::Info, "Hello");
+        assert_eq!(res.to_string(), "* Notice Info Hello");
+
+        let res = Response::notice(NoticeLevel::Error, "Server is shutting down");
+        assert_eq!(
+            res.to_string(),
+            "* Notice Error \"Server is shutting down\""
+        );
+    }
+
+    #[test]
+    fn test_response_list() {
+        let res = Response::success(Some("A000".into()), encode_list(&["Dessera", "Dark Star"]));
+        assert_eq!(
+            res.to_string(),
+            "A000 Success \"Dessera \\\"Dark Star\\\"\""
+        );
+
+        let resp = Response::try_from(res.to_string()).unwrap();
+        match resp.body {
+            ResponseBody::Success(msg) => {
+                assert_eq!(
+                    decode_list(msg.as_deref()).unwrap(),
+                    vec!["Dessera", "Dark Star"]
+                );
+            }
+            _ => panic!("Mismatched response, need Success but others found"),
+        }
+
+        assert_eq!(encode_list(&[] as &[&str]), None);
+        assert!(decode_list(None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_response_user_info() {
+        let info = UserInfo {
+            name: "Dessera".into(),
+            presence: Presence::Offline,
+            last_seen: Some(1700000000),
+            groups: vec!["Team".into(), "Night Owls".into()],
+        };
+
+        let res = Response::success(Some("A000".into()), Some(info.to_string()));
+        let resp = Response::try_from(res.to_string()).unwrap();
+        match resp.body {
+            ResponseBody::Success(Some(msg)) => {
+                assert_eq!(UserInfo::try_from(msg).unwrap(), info);
+            }
+            _ => panic!("Mismatched response, need Success but others found"),
+        }
+
+        let info = UserInfo::try_from("Scarlet Online -").unwrap();
+        assert_eq!(info.presence, Presence::Online);
+        assert_eq!(info.last_seen, None);
+        assert!(info.groups.is_empty());
+
+        assert!(UserInfo::try_from("Scarlet Busy -").is_err());
+        assert!(UserInfo::try_from("Scarlet Online Yesterday").is_err());
+    }
+
+    #[test]
+    fn test_response_blocks() {
+        let entries = vec![
+            BlockEntry {
+                name: "Dessera".into(),
+                policy: BlockPolicy::Silent,
+            },
+            BlockEntry {
+                name: "Night Owl".into(),
+                policy: BlockPolicy::Reject,
+            },
+        ];
+        let items: Vec<String> = entries.iter().map(|entry| entry.to_string()).collect();
+
+        let res = Response::success(Some("A000".into()), encode_list(&items));
+        let resp = Response::try_from(res.to_string()).unwrap();
+        match resp.body {
+            ResponseBody::Success(msg) => {
+                let decoded: Vec<BlockEntry> = decode_list(msg.as_deref())
+                    .unwrap()
+                    .into_iter()
+                    .map(|item| BlockEntry::try_from(item).unwrap())
+                    .collect();
+                assert_eq!(decoded, entries);
+            }
+            _ => panic!("Mismatched response, need Success but others found"),
+        }
+
+        assert!(BlockEntry::try_from("Dessera").is_err());
+        assert!(BlockEntry::try_from("Dessera Ignore").is_err());
+    }
+
+    #[test]
+    fn test_response_hello() {
+        let res = Response::hello(4096);
+        assert_eq!(
+            res.to_string(),
+            "* Hello 1 4096 ids ack presence echo resume"
+        );
+
+        let resp = Response::try_from("* Hello 2 0 ids zstd echo").unwrap();
+        match resp.body {
+            ResponseBody::Hello(version, max_line, caps) => {
+                assert_eq!(version, 2);
+                assert_eq!(max_line, 0);
+                assert_eq!(caps, vec![Capability::Ids, Capability::Echo]);
+            }
+            _ => panic!("Mismatched response, need Hello but others found"),
+        }
+
+        assert!(Response::try_from("* Hello").is_err());
+        assert!(Response::try_from("* Hello 1").is_err());
+        assert!(Response::try_from("* Hello One 4096 ids").is_err());
+        assert!(Response::try_from("* Hello 1 Long ids").is_err());
+    }
+
+    #[test]
+    fn test_response_ping() {
+        let res = Response::ping("0a1b");
+        assert_eq!(res.to_string(), "* Ping 0a1b");
+
+        let resp = Response::try_from(res.to_string()).unwrap();
+        match resp.body {
+            ResponseBody::Ping(token) => assert_eq!(token, "0a1b"),
+            _ => panic!("Mismatched response, need Ping but others found"),
+        }
+
+        assert!(Response::try_from("* Ping").is_err());
+    }
+
+    #[test]
+    fn test_response_history() {
+        let entries = [
+            HistoryEntry {
+                id: 1,
+                time: 1700000000,
+                sender: "Dessera".into(),
+                target: "G:Team".into(),
+                msg: "Hello everyone".into(),
+            },
+            HistoryEntry::new(2, "Scarlet", "G:Team", "Hi"),
+        ];
+
+        let payload: Vec<String> = entries.iter().map(|entry| entry.to_string()).collect();
+        let res = Response::success(Some("A000".into()), encode_list(&payload));
+        let resp = Response::try_from(res.to_string()).unwrap();
+        match resp.body {
+            ResponseBody::Success(msg) => {
+                let decoded: Vec<HistoryEntry> = decode_list(msg.as_deref())
+                    .unwrap()
+                    .into_iter()
+                    .map(|entry| HistoryEntry::try_from(entry).unwrap())
+                    .collect();
+                assert_eq!(decoded, entries);
+            }
+            _ => panic!("Mismatched response, need Success but others found"),
+        }
+
+        assert!(HistoryEntry::try_from("1 1700000000 Dessera Scarlet").is_err());
+        assert!(HistoryEntry::try_from("One 1700000000 Dessera Scarlet Hello").is_err());
+    }
+
+    #[test]
+    fn test_response_display_presence() {
+        let res = Response::presence("Scarlet", Presence::Online);
+        assert_eq!(res.to_string(), "* Presence Scarlet Online");
+
+        let res = Response::presence("Scarlet", Presence::Offline);
+        assert_eq!(res.to_string(), "* Presence Scarlet Offline");
+    }
+
+    #[test]
+    fn test_response_display_sent() {
+        let res = Response::sent("Scarlet", "Good night");
+        assert_eq!(res.to_string(), "* Sent Scarlet \"Good night\"");
     }
 }
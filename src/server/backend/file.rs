@@ -0,0 +1,1080 @@
+use crate::{
+    QuipError, QuipResult,
+    data::{BackendData, Group, GroupAction, User, UserRole},
+    response::{BlockEntry, BlockPolicy, HistoryEntry, Response, UserInfo},
+    server::{
+        backend::{Backend, MemoryBackend, RecordedEntry},
+        config::{HistoryRetention, OfflinePolicy},
+        connection::{ConnectionRef, QueuedResponse, unix_time},
+    },
+};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::Mutex,
+};
+
+const SNAPSHOT_FILE: &str = "snapshot.json";
+const SNAPSHOT_TEMP_FILE: &str = "snapshot.json.tmp";
+const JOURNAL_FILE: &str = "journal.log";
+
+/// Default count of journal entries before compaction.
+pub const DEFAULT_COMPACT_THRESHOLD: usize = 1024;
+
+/// Count of message IDs reserved by a journal entry, so that IDs are not
+/// recorded one by one.
+const MESSAGE_ID_BLOCK: u64 = 1024;
+
+/// Change of backend state, stored as a line of JSON in journal.
+#[derive(Debug, Serialize, Deserialize)]
+enum JournalEntry {
+    /// A user was created or updated.
+    User(User),
+    /// A group was created or updated.
+    Group(Group),
+    /// A group was destroyed.
+    GroupDestroy(String),
+    /// A response was cached for an offline user at the time.
+    PushAt(String, String, u64),
+    /// Cached responses of a user were delivered.
+    Drain(String),
+    /// Oldest cached responses of a user were removed, because they expired
+    /// or the cache was full.
+    Trim(String, usize),
+    /// Message IDs less than the value were reserved.
+    ReserveIds(u64),
+    /// A message was recorded in history.
+    History(HistoryRecord),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JournalRecord {
+    seq: u64,
+    entry: JournalEntry,
+}
+
+/// Full backend state, entries in journal with `seq` not greater than the
+/// snapshot were already applied.
+#[derive(Debug, Serialize, Deserialize)]
+struct Snapshot {
+    seq: u64,
+    data: BackendData,
+    queues: HashMap<String, Vec<CachedResponse>>,
+    #[serde(default)]
+    next_message_id: u64,
+    #[serde(default)]
+    history: Vec<HistoryRecord>,
+}
+
+/// Message in history with users it is hidden from, see [`RecordedEntry`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct HistoryRecord(String, Vec<String>);
+
+impl From<&RecordedEntry> for HistoryRecord {
+    fn from(recorded: &RecordedEntry) -> Self {
+        HistoryRecord(recorded.entry.to_string(), recorded.hidden.clone())
+    }
+}
+
+/// Response cached for an offline user in snapshot with the time it was
+/// cached.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedResponse(String, u64);
+
+impl From<&QueuedResponse> for CachedResponse {
+    fn from(queued: &QueuedResponse) -> Self {
+        CachedResponse(queued.resp.to_string(), queued.time)
+    }
+}
+
+impl Snapshot {
+    fn apply(&mut self, entry: JournalEntry) {
+        match entry {
+            JournalEntry::User(user) => self.data.upsert_user(user),
+            JournalEntry::Group(group) => self.data.upsert_group(group),
+            JournalEntry::GroupDestroy(name) => self.data.remove_group(&name),
+            JournalEntry::ReserveIds(next) => {
+                self.next_message_id = self.next_message_id.max(next);
+            }
+            JournalEntry::History(entry) => self.history.push(entry),
+            entry => apply_queue(&mut self.queues, &entry),
+        }
+    }
+}
+
+/// Apply an entry of cached responses to persisted queues, other entries are
+/// ignored.
+fn apply_queue(queues: &mut HashMap<String, Vec<CachedResponse>>, entry: &JournalEntry) {
+    match entry {
+        JournalEntry::PushAt(name, resp, time) => queues
+            .entry(name.clone())
+            .or_default()
+            .push(CachedResponse(resp.clone(), *time)),
+        JournalEntry::Drain(name) => {
+            queues.remove(name);
+        }
+        JournalEntry::Trim(name, count) => {
+            if let Some(queue) = queues.get_mut(name) {
+                queue.drain(..(*count).min(queue.len()));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Journal file with persisted cached responses, which are written to
+/// snapshot when compacting.
+///
+/// Cached responses in [`MemoryBackend`] may not be persisted yet, or were
+/// taken over by a session but not written to client yet.
+struct Journal {
+    file: File,
+    seq: u64,
+    count: usize,
+    reserved_id: u64,
+    queues: HashMap<String, Vec<CachedResponse>>,
+}
+
+/// File backend implementation.
+///
+/// Users, groups, cached responses of offline users and message history are
+/// kept in a [`MemoryBackend`], and persisted to a directory with a snapshot and an
+/// append-only journal. The journal is merged into the snapshot when it grows
+/// too large, or when the backend is opened.
+pub struct FileBackend {
+    memory: MemoryBackend,
+    dir: PathBuf,
+    journal: Mutex<Journal>,
+    compact_threshold: usize,
+}
+
+impl FileBackend {
+    /// Open a [`FileBackend`] in a directory with default limits, see
+    /// [`FileBackend::open_with`].
+    pub async fn open(dir: impl AsRef<Path>, seed: BackendData) -> QuipResult<Self> {
+        Self::open_with(dir, seed, Default::default(), Default::default()).await
+    }
+
+    /// Open a [`FileBackend`] in a directory, the seed data is used if there
+    /// is no snapshot in the directory.
+    ///
+    /// Limits are applied when replaying history and compacting, so they can
+    /// not be changed after opened.
+    pub async fn open_with(
+        dir: impl AsRef<Path>,
+        seed: BackendData,
+        retention: HistoryRetention,
+        policy: OfflinePolicy,
+    ) -> QuipResult<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).await?;
+
+        let mut snapshot = match fs::read(dir.join(SNAPSHOT_FILE)).await {
+            Ok(contents) => serde_json::from_slice::<Snapshot>(&contents)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Snapshot {
+                seq: 0,
+                data: seed,
+                queues: HashMap::new(),
+                next_message_id: 0,
+                history: Vec::new(),
+            },
+            Err(err) => return Err(err.into()),
+        };
+
+        let seq = replay_journal(&dir, &mut snapshot).await?;
+
+        let memory = MemoryBackend::from_data(snapshot.data)?
+            .with_history_retention(retention)
+            .with_offline_policy(policy);
+        let reserved_id = snapshot.next_message_id.max(1);
+        memory.advance_message_id(reserved_id);
+        for HistoryRecord(entry, hidden) in snapshot.history {
+            memory
+                .record_history(HistoryEntry::try_from(entry)?, hidden)
+                .await?;
+        }
+        for (name, queue) in snapshot.queues.iter() {
+            for CachedResponse(resp, time) in queue {
+                memory
+                    .restore_cached(name, Response::try_from(resp.clone())?, *time)
+                    .await?;
+            }
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(JOURNAL_FILE))
+            .await?;
+
+        let backend = Self {
+            memory,
+            dir,
+            journal: Mutex::new(Journal {
+                file,
+                seq,
+                count: 0,
+                reserved_id,
+                queues: snapshot.queues,
+            }),
+            compact_threshold: DEFAULT_COMPACT_THRESHOLD,
+        };
+        backend.compact().await?;
+
+        info!("File backend was opened in {}", backend.dir.display());
+
+        Ok(backend)
+    }
+
+    /// Set count of journal entries before compaction.
+    pub fn with_compact_threshold(mut self, threshold: usize) -> Self {
+        self.compact_threshold = threshold.max(1);
+        self
+    }
+
+    /// Merge journal into snapshot.
+    pub async fn compact(&self) -> QuipResult<()> {
+        let mut journal = self.journal.lock().await;
+        self.compact_inner(&mut journal).await
+    }
+
+    async fn compact_inner(&self, journal: &mut Journal) -> QuipResult<()> {
+        let snapshot = Snapshot {
+            seq: journal.seq,
+            data: self.memory.snapshot().await,
+            queues: journal.queues.clone(),
+            next_message_id: journal.reserved_id,
+            history: self
+                .memory
+                .history()
+                .await
+                .iter()
+                .map(HistoryRecord::from)
+                .collect(),
+        };
+
+        // Write to a temporary file first, so that the old snapshot is still
+        // valid if crashed.
+        let temp = self.dir.join(SNAPSHOT_TEMP_FILE);
+        let mut file = File::create(&temp).await?;
+        file.write_all(&serde_json::to_vec(&snapshot)?).await?;
+        file.sync_all().await?;
+        fs::rename(&temp, self.dir.join(SNAPSHOT_FILE)).await?;
+        File::open(&self.dir).await?.sync_all().await?;
+
+        journal.file.set_len(0).await?;
+        journal.file.sync_all().await?;
+        journal.count = 0;
+
+        Ok(())
+    }
+
+    async fn record(&self, journal: &mut Journal, entry: JournalEntry) -> QuipResult<()> {
+        apply_queue(&mut journal.queues, &entry);
+        journal.seq += 1;
+
+        let record = JournalRecord {
+            seq: journal.seq,
+            entry,
+        };
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+
+        journal.file.write_all(&line).await?;
+        journal.file.sync_data().await?;
+        journal.count += 1;
+
+        Ok(())
+    }
+
+    async fn maybe_compact(&self, journal: &mut Journal) -> QuipResult<()> {
+        if journal.count >= self.compact_threshold {
+            self.compact_inner(journal).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn record_user(&self, journal: &mut Journal, name: &str) -> QuipResult<()> {
+        match self.memory.find_user(name).await {
+            Some(user) => self.record(journal, JournalEntry::User(user)).await?,
+            None => return Err(QuipError::NotFound(format!("No user named {}", name))),
+        };
+
+        self.maybe_compact(journal).await
+    }
+
+    /// Record unacknowledged messages requeued to the cache of an offline
+    /// user, like cached by [`Backend::deliver`].
+    async fn record_requeued(&self, name: &str, requeued: Vec<Response>) -> QuipResult<()> {
+        if requeued.is_empty() {
+            return Ok(());
+        }
+
+        let mut journal = self.journal.lock().await;
+        let time = unix_time();
+        for resp in requeued {
+            let entry = JournalEntry::PushAt(name.into(), resp.to_string(), time);
+            self.record(&mut journal, entry).await?;
+        }
+
+        self.maybe_compact(&mut journal).await
+    }
+
+    async fn record_group(&self, journal: &mut Journal, name: &str) -> QuipResult<()> {
+        match self.memory.find_group_data(name).await {
+            Some(group) => self.record(journal, JournalEntry::Group(group)).await?,
+            None => return Err(QuipError::NotFound(format!("No group named {}", name))),
+        };
+
+        self.maybe_compact(journal).await
+    }
+}
+
+/// Apply journal to snapshot, returns sequence of the last entry.
+///
+/// An incomplete line at the end of journal is caused by crash when writing,
+/// so it is ignored with entries after it.
+async fn replay_journal(dir: &Path, snapshot: &mut Snapshot) -> QuipResult<u64> {
+    let contents = match fs::read(dir.join(JOURNAL_FILE)).await {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(snapshot.seq),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut seq = snapshot.seq;
+    for line in contents.split(|ch| *ch == b'\n') {
+        if line.is_empty() {
+            continue;
+        }
+
+        let record = match serde_json::from_slice::<JournalRecord>(line) {
+            Ok(record) => record,
+            Err(err) => {
+                warn!("Journal is truncated at entry {}: {}", seq + 1, err);
+                break;
+            }
+        };
+
+        if record.seq <= seq {
+            continue;
+        }
+
+        seq = record.seq;
+        snapshot.apply(record.entry);
+    }
+
+    Ok(seq)
+}
+
+impl Backend for FileBackend {
+    async fn load_conn(&self, name: &str, password: &str) -> QuipResult<ConnectionRef> {
+        self.memory.load_conn(name, password).await
+    }
+
+    async fn unload_conn(&self, name: &str, id: u64) -> QuipResult<()> {
+        let requeued = self.memory.unload_cached(name, id).await?;
+        self.record_requeued(name, requeued).await
+    }
+
+    async fn detach_conn(&self, name: &str, id: u64) -> QuipResult<()> {
+        let requeued = self.memory.detach_cached(name, id).await?;
+        self.record_requeued(name, requeued).await
+    }
+
+    async fn resume_conn(&self, token: &str) -> QuipResult<ConnectionRef> {
+        self.memory.resume_conn(token).await
+    }
+
+    async fn expire_conn(&self, name: &str, token: &str) -> QuipResult<()> {
+        self.memory.expire_conn(name, token).await
+    }
+
+    // Passwords are hashed before locking journal, the user is recorded as
+    // it is when journal is locked.
+    async fn create_user(&self, name: &str, password: &str) -> QuipResult<()> {
+        self.memory.create_user(name, password).await?;
+
+        let mut journal = self.journal.lock().await;
+        self.record_user(&mut journal, name).await
+    }
+
+    async fn update_password(&self, name: &str, old: &str, new: &str) -> QuipResult<()> {
+        self.memory.update_password(name, old, new).await?;
+
+        let mut journal = self.journal.lock().await;
+        self.record_user(&mut journal, name).await
+    }
+
+    async fn find_conns(&self, name: &str) -> QuipResult<Vec<ConnectionRef>> {
+        self.memory.find_conns(name).await
+    }
+
+    async fn ensure_conns(&self, name: &str) -> QuipResult<Vec<ConnectionRef>> {
+        self.memory.ensure_conns(name).await
+    }
+
+    async fn deliver(&self, name: &str, resp: Response) -> QuipResult<()> {
+        // Responses are pushed before locking journal, so that slow sessions
+        // do not block it.
+        let line = resp.to_string();
+        let removed = match self.memory.deliver_cached(name, resp).await? {
+            Some(removed) => removed,
+            None => return Ok(()),
+        };
+
+        let mut journal = self.journal.lock().await;
+        if removed > 0 {
+            self.record(&mut journal, JournalEntry::Trim(name.into(), removed))
+                .await?;
+        }
+
+        let entry = JournalEntry::PushAt(name.into(), line, unix_time());
+        self.record(&mut journal, entry).await?;
+        self.maybe_compact(&mut journal).await
+    }
+
+    async fn confirm_cached(&self, name: &str) -> QuipResult<()> {
+        let mut journal = self.journal.lock().await;
+
+        if journal.queues.contains_key(name) {
+            self.record(&mut journal, JournalEntry::Drain(name.into()))
+                .await?;
+            self.maybe_compact(&mut journal).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn next_message_id(&self) -> QuipResult<u64> {
+        let mut journal = self.journal.lock().await;
+
+        let id = self.memory.next_message_id().await?;
+        if id >= journal.reserved_id {
+            journal.reserved_id = id + MESSAGE_ID_BLOCK;
+            let entry = JournalEntry::ReserveIds(journal.reserved_id);
+            self.record(&mut journal, entry).await?;
+            self.maybe_compact(&mut journal).await?;
+        }
+
+        Ok(id)
+    }
+
+    async fn record_history(&self, entry: HistoryEntry, hidden: Vec<String>) -> QuipResult<()> {
+        if !self.memory.history_enabled() {
+            return Ok(());
+        }
+
+        let mut journal = self.journal.lock().await;
+
+        let record = HistoryRecord(entry.to_string(), hidden.clone());
+        self.record(&mut journal, JournalEntry::History(record))
+            .await?;
+        self.memory.record_history(entry, hidden).await?;
+        self.maybe_compact(&mut journal).await
+    }
+
+    async fn find_history(
+        &self,
+        name: &str,
+        target: &str,
+        before: Option<u64>,
+        limit: usize,
+    ) -> QuipResult<Vec<HistoryEntry>> {
+        self.memory.find_history(name, target, before, limit).await
+    }
+
+    async fn cache_conns(&self) -> QuipResult<()> {
+        let queues = self.memory.cache_sessions().await;
+        let mut journal = self.journal.lock().await;
+
+        // Persisted responses of the users are queued in their sessions if not
+        // written yet, so they are replaced.
+        for (name, queue) in queues {
+            if journal.queues.contains_key(&name) {
+                self.record(&mut journal, JournalEntry::Drain(name.clone()))
+                    .await?;
+            }
+            for queued in queue {
+                let entry =
+                    JournalEntry::PushAt(name.clone(), queued.resp.to_string(), queued.time);
+                self.record(&mut journal, entry).await?;
+            }
+        }
+
+        self.maybe_compact(&mut journal).await
+    }
+
+    async fn persist(&self) -> QuipResult<()> {
+        self.compact().await
+    }
+
+    async fn online_users(&self) -> QuipResult<Vec<String>> {
+        self.memory.online_users().await
+    }
+
+    async fn find_user_info(&self, name: &str) -> QuipResult<UserInfo> {
+        self.memory.find_user_info(name).await
+    }
+
+    async fn presence_audience(&self, name: &str) -> QuipResult<Vec<String>> {
+        self.memory.presence_audience(name).await
+    }
+
+    async fn subscribe(&self, name: &str, target: &str) -> QuipResult<()> {
+        self.memory.subscribe(name, target).await
+    }
+
+    async fn unsubscribe(&self, name: &str, target: &str) -> QuipResult<()> {
+        self.memory.unsubscribe(name, target).await
+    }
+
+    async fn block(&self, name: &str, target: &str, policy: BlockPolicy) -> QuipResult<()> {
+        let mut journal = self.journal.lock().await;
+
+        self.memory.block(name, target, policy).await?;
+        self.record_user(&mut journal, name).await
+    }
+
+    async fn unblock(&self, name: &str, target: &str) -> QuipResult<()> {
+        let mut journal = self.journal.lock().await;
+
+        self.memory.unblock(name, target).await?;
+        self.record_user(&mut journal, name).await
+    }
+
+    async fn find_blocks(&self, name: &str) -> QuipResult<Vec<BlockEntry>> {
+        self.memory.find_blocks(name).await
+    }
+
+    async fn find_block(&self, name: &str, sender: &str) -> QuipResult<Option<BlockPolicy>> {
+        self.memory.find_block(name, sender).await
+    }
+
+    async fn ban_user(&self, name: &str, until: Option<u64>) -> QuipResult<()> {
+        let mut journal = self.journal.lock().await;
+
+        self.memory.ban_user(name, until).await?;
+        self.record_user(&mut journal, name).await
+    }
+
+    async fn mute_user(&self, name: &str, until: Option<u64>) -> QuipResult<()> {
+        let mut journal = self.journal.lock().await;
+
+        self.memory.mute_user(name, until).await?;
+        self.record_user(&mut journal, name).await
+    }
+
+    async fn find_mute(&self, name: &str) -> QuipResult<Option<u64>> {
+        self.memory.find_mute(name).await
+    }
+
+    async fn close_conns(&self, name: &str, notice: Response) -> QuipResult<usize> {
+        self.memory.close_conns(name, notice).await
+    }
+
+    async fn find_group(&self, name: &str) -> QuipResult<Vec<String>> {
+        self.memory.find_group(name).await
+    }
+
+    async fn create_group(&self, owner: &str, name: &str) -> QuipResult<()> {
+        let mut journal = self.journal.lock().await;
+
+        self.memory.create_group(owner, name).await?;
+        self.record_group(&mut journal, name).await
+    }
+
+    async fn update_group(
+        &self,
+        operator: &str,
+        group: &str,
+        action: GroupAction<'_>,
+    ) -> QuipResult<()> {
+        let mut journal = self.journal.lock().await;
+
+        self.memory.update_group(operator, group, action).await?;
+        match action {
+            GroupAction::Destroy => {
+                self.record(&mut journal, JournalEntry::GroupDestroy(group.into()))
+                    .await?;
+                self.maybe_compact(&mut journal).await
+            }
+            _ => self.record_group(&mut journal, group).await,
+        }
+    }
+
+    async fn find_user_role(&self, name: &str) -> QuipResult<UserRole> {
+        self.memory.find_user_role(name).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        data::{FOREVER, hash_password},
+        response::GroupRole,
+        server::config::OfflineOverflow,
+    };
+
+    fn seed() -> BackendData {
+        let users = ["Dessera", "Scarlet"]
+            .iter()
+            .map(|name| User {
+                name: name.to_string(),
+                password: hash_password("Pass").unwrap(),
+                role: UserRole::Member,
+                blocks: Default::default(),
+                banned_until: None,
+                muted_until: None,
+            })
+            .collect();
+
+        BackendData::new(users, vec![])
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("quip-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_file_backend_recover() {
+        let dir = temp_dir("recover");
+
+        {
+            let backend = FileBackend::open(&dir, seed()).await.unwrap();
+            backend.create_group("Dessera", "Team").await.unwrap();
+            backend
+                .update_group("Dessera", "Team", GroupAction::Add("Scarlet"))
+                .await
+                .unwrap();
+            backend
+                .deliver("Scarlet", Response::recv(None, "Dessera", "Hello"))
+                .await
+                .unwrap();
+        }
+
+        // Simulate a crash when writing the journal.
+        let mut journal = std::fs::OpenOptions::new()
+            .append(true)
+            .open(dir.join(JOURNAL_FILE))
+            .unwrap();
+        std::io::Write::write_all(&mut journal, b"{\"seq\":").unwrap();
+
+        let backend = FileBackend::open(&dir, BackendData::new(vec![], vec![]))
+            .await
+            .unwrap();
+        let mut users = backend.find_group("Team").await.unwrap();
+        users.sort();
+        assert_eq!(users, vec!["Dessera", "Scarlet"]);
+
+        let queues = backend.memory.offline_queues().await;
+        assert_eq!(queues["Scarlet"].len(), 1);
+        assert_eq!(
+            queues["Scarlet"][0].resp.to_string(),
+            "* Recv Dessera Hello"
+        );
+
+        // Cached responses are kept until written to client.
+        backend.load_conn("Scarlet", "Pass").await.unwrap();
+        drop(backend);
+
+        let backend = FileBackend::open(&dir, BackendData::new(vec![], vec![]))
+            .await
+            .unwrap();
+        assert_eq!(backend.memory.offline_queues().await["Scarlet"].len(), 1);
+
+        let conn = backend.load_conn("Scarlet", "Pass").await.unwrap();
+        assert!(conn.lock().await.restored);
+        backend.confirm_cached("Scarlet").await.unwrap();
+        drop(backend);
+
+        let backend = FileBackend::open(&dir, BackendData::new(vec![], vec![]))
+            .await
+            .unwrap();
+        assert!(backend.memory.offline_queues().await.is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_file_backend_message_id() {
+        let dir = temp_dir("message-id");
+
+        let last = {
+            let backend = FileBackend::open(&dir, seed()).await.unwrap();
+            assert_eq!(backend.next_message_id().await.unwrap(), 1);
+            backend.next_message_id().await.unwrap()
+        };
+
+        // IDs are never reused, even if the server did not exit gracefully.
+        let backend = FileBackend::open(&dir, seed()).await.unwrap();
+        let id = backend.next_message_id().await.unwrap();
+        assert!(id > last);
+
+        backend.persist().await.unwrap();
+        drop(backend);
+
+        let backend = FileBackend::open(&dir, seed()).await.unwrap();
+        assert!(backend.next_message_id().await.unwrap() > id);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_file_backend_history() {
+        let dir = temp_dir("history");
+        let retention = HistoryRetention { limit: 2, ttl: 0 };
+
+        {
+            let backend = FileBackend::open_with(&dir, seed(), retention, Default::default())
+                .await
+                .unwrap();
+            for (id, msg) in [(1, "One"), (2, "Two"), (3, "Three")] {
+                let entry = HistoryEntry::new(id, "Dessera", "Scarlet", msg);
+                backend.record_history(entry, Vec::new()).await.unwrap();
+            }
+        }
+
+        let backend = FileBackend::open_with(&dir, seed(), retention, Default::default())
+            .await
+            .unwrap();
+        let entries = backend
+            .find_history("Scarlet", "Dessera", None, 10)
+            .await
+            .unwrap();
+        let ids: Vec<u64> = entries.iter().map(|entry| entry.id).collect();
+        assert_eq!(ids, vec![2, 3]);
+
+        let entries = backend
+            .find_history("Dessera", "Scarlet", Some(3), 10)
+            .await
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].msg, "Two");
+
+        assert!(
+            backend
+                .find_history("Dessera", "Nobody", None, 10)
+                .await
+                .is_err()
+        );
+        assert!(
+            backend
+                .find_history("Dessera", "G:Team", None, 10)
+                .await
+                .is_err()
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_file_backend_history_restart() {
+        let dir = temp_dir("history-restart");
+        let limit = HistoryRetention::default().limit + 100;
+        let retention = HistoryRetention { limit, ttl: 0 };
+        let count = limit as u64 - 50;
+
+        {
+            let backend = FileBackend::open_with(&dir, seed(), retention, Default::default())
+                .await
+                .unwrap()
+                .with_compact_threshold(count as usize / 2);
+            for id in 1..=count {
+                let entry = HistoryEntry::new(id, "Dessera", "Scarlet", "Hello");
+                let hidden = match id % 2 {
+                    0 => vec!["Scarlet".into()],
+                    _ => Vec::new(),
+                };
+                backend.record_history(entry, hidden).await.unwrap();
+            }
+        }
+
+        // History above the default limit is kept after restarting, so are
+        // users it is hidden from.
+        for _ in 0..2 {
+            let backend = FileBackend::open_with(&dir, seed(), retention, Default::default())
+                .await
+                .unwrap();
+            let entries = backend
+                .find_history("Dessera", "Scarlet", None, limit)
+                .await
+                .unwrap();
+            assert_eq!(entries.len() as u64, count);
+
+            let entries = backend
+                .find_history("Scarlet", "Dessera", None, limit)
+                .await
+                .unwrap();
+            assert_eq!(entries.len() as u64, count - count / 2);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_file_backend_offline_queue() {
+        let dir = temp_dir("offline");
+        let policy = OfflinePolicy {
+            limit: 2,
+            ttl: 60,
+            overflow: OfflineOverflow::DropOldest,
+        };
+
+        {
+            let backend = FileBackend::open_with(&dir, seed(), Default::default(), policy)
+                .await
+                .unwrap();
+            for msg in ["One", "Two", "Three"] {
+                let resp = Response::recv(None, "Dessera", msg);
+                backend.deliver("Scarlet", resp).await.unwrap();
+            }
+        }
+
+        let policy = OfflinePolicy {
+            overflow: OfflineOverflow::Reject,
+            ..policy
+        };
+        let backend = FileBackend::open_with(&dir, seed(), Default::default(), policy)
+            .await
+            .unwrap();
+        let queues = backend.memory.offline_queues().await;
+        let msgs: Vec<String> = queues["Scarlet"]
+            .iter()
+            .map(|queued| queued.resp.to_string())
+            .collect();
+        assert_eq!(msgs, vec!["* Recv Dessera Two", "* Recv Dessera Three"]);
+
+        let resp = Response::recv(None, "Dessera", "Four");
+        assert!(matches!(
+            backend.deliver("Scarlet", resp).await,
+            Err(QuipError::QueueFull(_))
+        ));
+
+        // Expired responses make room for new ones.
+        for conn in backend.find_conns("Scarlet").await.unwrap() {
+            let conn = conn.lock().await;
+            conn.queue.lock().await.front_mut().unwrap().time = 0;
+        }
+        let resp = Response::recv(None, "Dessera", "Four");
+        backend.deliver("Scarlet", resp).await.unwrap();
+        assert_eq!(backend.memory.offline_queues().await["Scarlet"].len(), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_file_backend_blocks() {
+        let dir = temp_dir("blocks");
+
+        {
+            let backend = FileBackend::open(&dir, seed()).await.unwrap();
+            backend.create_group("Dessera", "Team").await.unwrap();
+            backend
+                .update_group("Dessera", "Team", GroupAction::Add("Scarlet"))
+                .await
+                .unwrap();
+            backend
+                .block("Scarlet", "Dessera", BlockPolicy::Reject)
+                .await
+                .unwrap();
+            assert!(
+                backend
+                    .block("Scarlet", "Nobody", BlockPolicy::Silent)
+                    .await
+                    .is_err()
+            );
+        }
+
+        let backend = FileBackend::open(&dir, seed()).await.unwrap();
+        assert_eq!(
+            backend.find_block("Scarlet", "Dessera").await.unwrap(),
+            Some(BlockPolicy::Reject)
+        );
+        assert_eq!(
+            backend.find_block("Dessera", "Scarlet").await.unwrap(),
+            None
+        );
+        assert_eq!(backend.find_blocks("Scarlet").await.unwrap().len(), 1);
+
+        // Blocking users does not change groups.
+        let mut users = backend.find_group("Team").await.unwrap();
+        users.sort();
+        assert_eq!(users, vec!["Dessera", "Scarlet"]);
+
+        backend.unblock("Scarlet", "Dessera").await.unwrap();
+        assert!(matches!(
+            backend.unblock("Scarlet", "Dessera").await,
+            Err(QuipError::NotFound(_))
+        ));
+        assert!(backend.find_blocks("Scarlet").await.unwrap().is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    async fn group_role(backend: &FileBackend, name: &str) -> Option<GroupRole> {
+        let group = backend.memory.find_group_data("Team").await.unwrap();
+        group.role_of(name)
+    }
+
+    #[tokio::test]
+    async fn test_file_backend_group_role() {
+        let dir = temp_dir("group_role");
+
+        {
+            let backend = FileBackend::open(&dir, seed()).await.unwrap();
+            backend.create_group("Dessera", "Team").await.unwrap();
+            backend
+                .update_group("Dessera", "Team", GroupAction::Add("Scarlet"))
+                .await
+                .unwrap();
+            let action = GroupAction::SetRole("Scarlet", GroupRole::Moderator);
+            backend
+                .update_group("Dessera", "Team", action)
+                .await
+                .unwrap();
+            assert!(matches!(
+                backend
+                    .update_group("Scarlet", "Team", GroupAction::Destroy)
+                    .await,
+                Err(QuipError::Forbidden(_))
+            ));
+        }
+
+        let backend = FileBackend::open(&dir, seed()).await.unwrap();
+        assert_eq!(
+            group_role(&backend, "Scarlet").await,
+            Some(GroupRole::Moderator)
+        );
+
+        // The old owner becomes a member after transferring the ownership.
+        let action = GroupAction::SetRole("Scarlet", GroupRole::Owner);
+        backend
+            .update_group("Dessera", "Team", action)
+            .await
+            .unwrap();
+        assert_eq!(
+            group_role(&backend, "Dessera").await,
+            Some(GroupRole::Member)
+        );
+        assert_eq!(
+            group_role(&backend, "Scarlet").await,
+            Some(GroupRole::Owner)
+        );
+        assert!(
+            backend
+                .update_group(
+                    "Scarlet",
+                    "Team",
+                    GroupAction::SetRole("Nobody", GroupRole::Member)
+                )
+                .await
+                .is_err()
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_file_backend_moderation() {
+        let dir = temp_dir("moderation");
+
+        {
+            let backend = FileBackend::open(&dir, seed()).await.unwrap();
+            backend.ban_user("Scarlet", Some(FOREVER)).await.unwrap();
+            backend.mute_user("Dessera", Some(FOREVER)).await.unwrap();
+            assert!(backend.ban_user("Nobody", Some(FOREVER)).await.is_err());
+        }
+
+        let backend = FileBackend::open(&dir, seed()).await.unwrap();
+        assert!(matches!(
+            backend.load_conn("Scarlet", "Pass").await,
+            Err(QuipError::Forbidden(_))
+        ));
+        assert_eq!(backend.find_mute("Dessera").await.unwrap(), Some(FOREVER));
+
+        // Expired bans and mutes are ignored.
+        backend.ban_user("Scarlet", Some(0)).await.unwrap();
+        backend.mute_user("Dessera", None).await.unwrap();
+        assert!(backend.load_conn("Scarlet", "Pass").await.is_ok());
+        assert_eq!(backend.find_mute("Dessera").await.unwrap(), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_file_backend_cache_conns() {
+        let dir = temp_dir("cache-conns");
+
+        {
+            let backend = FileBackend::open(&dir, seed()).await.unwrap();
+            backend.load_conn("Scarlet", "Pass").await.unwrap();
+            backend
+                .deliver("Scarlet", Response::message(1, "Dessera", "Hello"))
+                .await
+                .unwrap();
+
+            // Messages of online users are not journaled until cached.
+            assert!(backend.journal.lock().await.queues.is_empty());
+            backend.cache_conns().await.unwrap();
+            backend.persist().await.unwrap();
+        }
+
+        let backend = FileBackend::open(&dir, seed()).await.unwrap();
+        let queues = backend.memory.offline_queues().await;
+        assert_eq!(queues["Scarlet"].len(), 1);
+        assert_eq!(
+            queues["Scarlet"][0].resp.to_string(),
+            "* Recv Dessera Hello 1"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_file_backend_requeue() {
+        let dir = temp_dir("requeue");
+        let policy = OfflinePolicy {
+            limit: 2,
+            ttl: 0,
+            overflow: OfflineOverflow::Reject,
+        };
+
+        {
+            let backend = FileBackend::open_with(&dir, seed(), Default::default(), policy)
+                .await
+                .unwrap();
+            let conn = backend.load_conn("Scarlet", "Pass").await.unwrap();
+            let (id, unacked) = {
+                let conn = conn.lock().await;
+                (conn.id, conn.unacked.clone())
+            };
+            for (id, msg) in [(1, "One"), (2, "Two"), (3, "Three")] {
+                let resp = Response::message(id, "Dessera", msg);
+                unacked.lock().await.insert(id, resp);
+            }
+
+            // The oldest message is dropped like a full cache.
+            backend.detach_conn("Scarlet", id).await.unwrap();
+        }
+
+        let backend = FileBackend::open(&dir, seed()).await.unwrap();
+        let queues = backend.memory.offline_queues().await;
+        let msgs: Vec<String> = queues["Scarlet"]
+            .iter()
+            .map(|queued| queued.resp.to_string())
+            .collect();
+        assert_eq!(msgs, vec!["* Recv Dessera Two 2", "* Recv Dessera Three 3"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,27 +1,70 @@
 use crate::{
     QuipError, QuipResult,
-    data::{BackendData, BackendQueryData},
+    data::{
+        BackendData, BackendQueryData, Group, GroupAction, QueryGroup, User, UserRole,
+        hash_password_blocking, verify_password_blocking,
+    },
+    request::GROUP_PREFIX,
+    response::{BlockEntry, BlockPolicy, GroupRole, HistoryEntry, Presence, Response, UserInfo},
     server::{
         backend::Backend,
-        connection::{Connection, ConnectionRef, ConnectionStatus},
+        config::{HistoryRetention, OfflineOverflow, OfflinePolicy},
+        connection::{
+            Connection, ConnectionRef, ConnectionStatus, QueuedResponse, presence_of, unix_time,
+        },
+    },
+};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
     },
 };
-use std::{collections::HashMap, sync::Arc};
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, RwLock};
 
 /// Memory backend implementation.
 ///
-/// All users are stored in memory with a [`HashMap`].
+/// All users are stored in memory with a [`HashMap`]. Connections of a user are
+/// either its authenticated sessions, or a single cache connection holding
+/// responses when the user is offline, which are limited by an
+/// [`OfflinePolicy`]. The last seen time, presence
+/// subscriptions and message history are not persisted, which only last until
+/// the server exits.
 pub struct MemoryBackend {
-    data: BackendQueryData,
-    conns: Arc<Mutex<HashMap<String, Arc<Mutex<Connection>>>>>,
+    data: RwLock<BackendQueryData>,
+    conns: Arc<Mutex<HashMap<String, Vec<ConnectionRef>>>>,
+    last_seen: Mutex<HashMap<String, u64>>,
+    next_message_id: AtomicU64,
+    subscribers: Mutex<HashMap<String, HashSet<String>>>,
+    history: Mutex<HashMap<ConversationKey, VecDeque<RecordedEntry>>>,
+    history_retention: HistoryRetention,
+    offline_policy: OfflinePolicy,
 }
 
+/// Message in history, which is hidden from users who silently blocked the
+/// sender when it was recorded.
+#[derive(Debug, Clone)]
+pub struct RecordedEntry {
+    pub entry: HistoryEntry,
+    pub hidden: Vec<String>,
+}
+
+/// Key of a conversation in history, which is the sorted names of both users
+/// for direct messages, or the group target with an empty name.
+type ConversationKey = (String, String);
+
 impl MemoryBackend {
     pub fn new(data: BackendQueryData) -> Self {
         Self {
-            data,
+            data: RwLock::new(data),
             conns: Arc::new(Mutex::new(HashMap::new())),
+            last_seen: Mutex::new(HashMap::new()),
+            next_message_id: AtomicU64::new(1),
+            subscribers: Mutex::new(HashMap::new()),
+            history: Mutex::new(HashMap::new()),
+            history_retention: HistoryRetention::default(),
+            offline_policy: OfflinePolicy::default(),
         }
     }
 
@@ -30,76 +73,1110 @@ impl MemoryBackend {
         let data = data.try_into()?;
         Ok(Self::new(data))
     }
-}
 
-impl Backend for MemoryBackend {
-    async fn load_conn(&self, name: &str, password: &str) -> QuipResult<ConnectionRef> {
-        match self.data.users.get(name) {
-            Some(user) => {
-                if user.password != password {
-                    return Err(QuipError::Authorize(format!(
-                        "Incorrect password for user {}",
-                        name
-                    )));
+    /// Set retention limits of message history.
+    pub fn with_history_retention(mut self, retention: HistoryRetention) -> Self {
+        self.history_retention = retention;
+        self
+    }
+
+    /// Set limits of responses cached for offline users.
+    pub fn with_offline_policy(mut self, policy: OfflinePolicy) -> Self {
+        self.offline_policy = policy;
+        self
+    }
+
+    /// Check if messages should be recorded in history.
+    pub fn history_enabled(&self) -> bool {
+        self.history_retention.limit > 0
+    }
+
+    /// Get messages in history of all conversations, sorted by ID.
+    pub async fn history(&self) -> Vec<RecordedEntry> {
+        let mut history = self.history.lock().await;
+
+        let mut entries = Vec::new();
+        for messages in history.values_mut() {
+            self.prune_history(messages);
+            entries.extend(messages.iter().cloned());
+        }
+        history.retain(|_, messages| !messages.is_empty());
+
+        entries.sort_by_key(|recorded| recorded.entry.id);
+        entries
+    }
+
+    /// Remove messages out of retention limits in a conversation.
+    fn prune_history(&self, messages: &mut VecDeque<RecordedEntry>) {
+        while messages.len() > self.history_retention.limit {
+            messages.pop_front();
+        }
+
+        if self.history_retention.ttl == 0 {
+            return;
+        }
+
+        let now = unix_time();
+        while messages
+            .front()
+            .is_some_and(|recorded| recorded.entry.time + self.history_retention.ttl < now)
+        {
+            messages.pop_front();
+        }
+    }
+
+    /// Get raw data of current users and groups.
+    pub async fn snapshot(&self) -> BackendData {
+        BackendData::from(&*self.data.read().await)
+    }
+
+    /// Find a user by name.
+    pub async fn find_user(&self, name: &str) -> Option<User> {
+        self.data
+            .read()
+            .await
+            .users
+            .get(name)
+            .map(|user| (**user).clone())
+    }
+
+    /// Find a group by name.
+    pub async fn find_group_data(&self, name: &str) -> Option<Group> {
+        self.data
+            .read()
+            .await
+            .groups
+            .get(name)
+            .map(|group| group.0.clone())
+    }
+
+    /// Make sure message IDs allocated later are not less than `next`.
+    pub fn advance_message_id(&self, next: u64) {
+        self.next_message_id.fetch_max(next, Ordering::Relaxed);
+    }
+
+    /// Record last seen time of a user if it has no online session.
+    async fn touch_last_seen(&self, name: &str, sessions: &[ConnectionRef]) {
+        if presence_of(sessions).await != Presence::Offline {
+            return;
+        }
+
+        let now = unix_time();
+        self.last_seen.lock().await.insert(name.into(), now);
+    }
+
+    /// Deliver a response to all sessions of a user, the count of removed
+    /// oldest cached responses is returned if it was cached because the user
+    /// is offline.
+    ///
+    /// Expired responses are removed before caching, and
+    /// [`QuipError::QueueFull`] is returned if the cache is still full and
+    /// the overflow policy rejects new responses.
+    pub async fn deliver_cached(&self, name: &str, resp: Response) -> QuipResult<Option<usize>> {
+        let conns = self.ensure_conns(name).await?;
+
+        let removed = match presence_of(&conns).await {
+            Presence::Offline => Some(self.make_room(name, &conns).await?),
+            _ => None,
+        };
+
+        for conn in conns {
+            conn.lock().await.push(resp.clone()).await;
+        }
+
+        Ok(removed)
+    }
+
+    /// Cache a response queued at `time` for a user, which ignores the
+    /// offline policy and is used to restore persisted responses.
+    pub async fn restore_cached(&self, name: &str, resp: Response, time: u64) -> QuipResult<()> {
+        for conn in self.ensure_conns(name).await? {
+            conn.lock().await.push_at(resp.clone(), time).await;
+        }
+        Ok(())
+    }
+
+    /// Make room for a new response in caches of an offline user, returns
+    /// count of responses removed from the first cache.
+    async fn make_room(&self, name: &str, conns: &[ConnectionRef]) -> QuipResult<usize> {
+        let policy = self.offline_policy;
+        let mut removed = None;
+
+        for conn in conns {
+            let conn = conn.lock().await;
+            let before = conn.queue.lock().await.len();
+            conn.expire_queue(policy.ttl).await;
+
+            let mut queue = conn.queue.lock().await;
+            if policy.limit > 0 && queue.len() >= policy.limit {
+                match policy.overflow {
+                    OfflineOverflow::Reject => {
+                        return Err(QuipError::QueueFull(format!(
+                            "Too many messages cached for {}",
+                            name
+                        )));
+                    }
+                    OfflineOverflow::DropOldest => {
+                        let excess = queue.len() + 1 - policy.limit;
+                        queue.drain(..excess);
+                    }
                 }
             }
+
+            removed.get_or_insert(before - queue.len());
+        }
+
+        Ok(removed.unwrap_or_default())
+    }
+
+    /// Unload a session like [`Backend::unload_conn`], returns messages
+    /// requeued to the cache if the user is offline now.
+    pub async fn unload_cached(&self, name: &str, id: u64) -> QuipResult<Vec<Response>> {
+        let mut conns = self.conns.lock().await;
+
+        let sessions = match conns.get_mut(name) {
+            Some(sessions) => sessions,
             None => return Err(QuipError::NotFound(format!("No user named {}", name))),
         };
 
+        let mut index = None;
+        for (i, conn) in sessions.iter().enumerate() {
+            if conn.lock().await.id == id {
+                index = Some(i);
+                break;
+            }
+        }
+
+        let requeued = match index {
+            Some(index) => remove_session(sessions, index, self.offline_policy.limit).await,
+            None => {
+                return Err(QuipError::NotFound(format!(
+                    "No session {} of {}",
+                    id, name
+                )));
+            }
+        };
+        self.touch_last_seen(name, sessions).await;
+
+        Ok(requeued)
+    }
+
+    /// Detach a session like [`Backend::detach_conn`], returns messages
+    /// requeued to the cache if the user is offline now.
+    pub async fn detach_cached(&self, name: &str, id: u64) -> QuipResult<Vec<Response>> {
+        let conns = self.conns.lock().await;
+
+        let sessions = match conns.get(name) {
+            Some(sessions) => sessions,
+            None => return Err(QuipError::NotFound(format!("No user named {}", name))),
+        };
+
+        let mut requeued = Vec::new();
+        for conn in sessions {
+            let mut conn = conn.lock().await;
+            if conn.id == id {
+                conn.status = ConnectionStatus::Cache;
+                requeued = conn.requeue_unacked(self.offline_policy.limit).await;
+                break;
+            }
+        }
+        self.touch_last_seen(name, sessions).await;
+
+        // Messages in sessions of online users are not cached for offline.
+        if presence_of(sessions).await != Presence::Offline {
+            requeued.clear();
+        }
+
+        Ok(requeued)
+    }
+
+    /// Cache all sessions which are not cached as if their sockets dropped,
+    /// returns messages queued in them by user, sorted by ID.
+    pub async fn cache_sessions(&self) -> HashMap<String, Vec<QueuedResponse>> {
+        let conns = self.conns.lock().await;
+        let mut queues = HashMap::new();
+
+        for (name, sessions) in conns.iter() {
+            // Sessions may share messages, which are merged by ID.
+            let mut messages = BTreeMap::new();
+            for conn in sessions {
+                let mut conn = conn.lock().await;
+                if conn.status == ConnectionStatus::Cache {
+                    continue;
+                }
+
+                conn.status = ConnectionStatus::Cache;
+                conn.requeue_unacked(self.offline_policy.limit).await;
+                for queued in conn.queue.lock().await.iter() {
+                    if let Some(id) = queued.resp.message_id() {
+                        messages.entry(id).or_insert_with(|| queued.clone());
+                    }
+                }
+            }
+
+            if !messages.is_empty() {
+                queues.insert(name.clone(), messages.into_values().collect());
+            }
+        }
+
+        queues
+    }
+
+    /// Get cached responses of all offline users.
+    pub async fn offline_queues(&self) -> HashMap<String, Vec<QueuedResponse>> {
+        let conns = self.conns.lock().await;
+        let mut queues = HashMap::new();
+
+        for (name, sessions) in conns.iter() {
+            // Sessions of an offline user cache the same responses since the
+            // user went offline, the first one is enough.
+            let conn = match sessions.first() {
+                Some(conn) if presence_of(sessions).await == Presence::Offline => conn,
+                _ => continue,
+            };
+
+            let conn = conn.lock().await;
+
+            let queue = conn.queue.lock().await;
+            if !queue.is_empty() {
+                queues.insert(name.clone(), queue.iter().cloned().collect());
+            }
+        }
+
+        queues
+    }
+}
+
+/// Remove a session of a user, the last one is kept to cache messages and
+/// other responses of it are dropped, so that the next session does not
+/// receive them. Returns messages requeued to the cache, see
+/// [`Connection::requeue_unacked`].
+async fn remove_session(
+    sessions: &mut Vec<ConnectionRef>,
+    index: usize,
+    limit: usize,
+) -> Vec<Response> {
+    if sessions.len() > 1 {
+        sessions.remove(index);
+        return Vec::new();
+    }
+
+    let mut conn = sessions[index].lock().await;
+    conn.status = ConnectionStatus::Cache;
+    conn.token = None;
+    conn.away = false;
+    conn.retain_messages().await;
+    conn.requeue_unacked(limit).await
+}
+
+/// Replace a user with the updated one.
+fn replace_user(data: &mut BackendQueryData, old: &Arc<User>, user: User) {
+    let user = Arc::new(user);
+    data.users.insert(user.name.clone(), user.clone());
+
+    // Groups hold the user by value, replace the old one.
+    for group in data.groups.values_mut() {
+        if group.1.remove(old) {
+            group.1.insert(user.clone());
+        }
+    }
+}
+
+/// Find key of the conversation of a message.
+fn conversation_key(sender: &str, target: &str) -> ConversationKey {
+    if target.starts_with(GROUP_PREFIX) {
+        return (target.into(), String::new());
+    }
+
+    match sender < target {
+        true => (sender.into(), target.into()),
+        false => (target.into(), sender.into()),
+    }
+}
+
+/// Find a group by name.
+fn find_group_mut<'a>(
+    data: &'a mut BackendQueryData,
+    group: &str,
+) -> QuipResult<&'a mut QueryGroup> {
+    data.groups
+        .get_mut(group)
+        .ok_or_else(|| QuipError::NotFound(format!("No group named {}", group)))
+}
+
+/// Add a user to a group.
+fn add_group_user(data: &mut BackendQueryData, group: &str, name: &str) -> QuipResult<()> {
+    let user = match data.users.get(name) {
+        Some(user) => user.clone(),
+        None => return Err(QuipError::NotFound(format!("No user named {}", name))),
+    };
+
+    let group = find_group_mut(data, group)?;
+    if !group.1.insert(user) {
+        return Err(QuipError::Duplicate(format!(
+            "User {} exists in group {}",
+            name, group.0.name
+        )));
+    }
+    group.0.users.push(name.into());
+
+    Ok(())
+}
+
+/// Remove a user from a group.
+fn remove_group_user(data: &mut BackendQueryData, group: &str, name: &str) -> QuipResult<()> {
+    let group = find_group_mut(data, group)?;
+    if !group.0.users.iter().any(|user| user == name) {
+        return Err(QuipError::NotFound(format!(
+            "No user named {} in group {}",
+            name, group.0.name
+        )));
+    }
+
+    group.0.users.retain(|user| user != name);
+    group.0.moderators.retain(|user| user != name);
+    group.1.retain(|user| user.name != name);
+    if group.0.owner.as_deref() == Some(name) {
+        group.0.owner = None;
+    }
+
+    Ok(())
+}
+
+/// Destroy a group.
+fn destroy_group(data: &mut BackendQueryData, group: &str) -> QuipResult<()> {
+    find_group_mut(data, group)?;
+    data.groups.remove(group);
+
+    Ok(())
+}
+
+/// Set role of a member in a group.
+fn set_group_role(
+    data: &mut BackendQueryData,
+    group: &str,
+    name: &str,
+    role: GroupRole,
+) -> QuipResult<()> {
+    let group = &mut find_group_mut(data, group)?.0;
+    if group.role_of(name).is_none() {
+        return Err(QuipError::NotFound(format!(
+            "No user named {} in group {}",
+            name, group.name
+        )));
+    }
+
+    group.moderators.retain(|user| user != name);
+    match role {
+        GroupRole::Owner => group.owner = Some(name.into()),
+        GroupRole::Moderator => group.moderators.push(name.into()),
+        GroupRole::Member if group.owner.as_deref() == Some(name) => group.owner = None,
+        GroupRole::Member => {}
+    }
+
+    Ok(())
+}
+
+impl Backend for MemoryBackend {
+    async fn load_conn(&self, name: &str, password: &str) -> QuipResult<ConnectionRef> {
+        // Unknown users are verified too, so that they take as long as known
+        // ones and look the same as incorrect passwords.
+        let user = self.find_user(name).await;
+        let hash = user.as_ref().map(|user| user.password.clone());
+        if !verify_password_blocking(hash, password).await {
+            return Err(QuipError::Authorize(format!(
+                "Incorrect name or password for user {}",
+                name
+            )));
+        }
+        if user.is_some_and(|user| user.is_banned(unix_time())) {
+            return Err(QuipError::Forbidden(format!("User {} is banned", name)));
+        }
+
         let mut conns = self.conns.lock().await;
+        let sessions = conns.entry(name.into()).or_default();
 
-        let conn = match conns.get(name) {
-            Some(conn) => {
-                let mut conn_handle = conn.lock().await;
+        // The first session takes over cached responses.
+        if presence_of(sessions).await == Presence::Offline
+            && let Some(conn) = sessions.first()
+        {
+            let mut conn_handle = conn.lock().await;
+            conn_handle.status = ConnectionStatus::Auth;
+            conn_handle.token = None;
+            conn_handle.away = false;
+            conn_handle.restored = true;
+            conn_handle.expire_queue(self.offline_policy.ttl).await;
+            return Ok(conn.clone());
+        }
+
+        let conn = Arc::new(Mutex::new(Connection::new(name, ConnectionStatus::Auth)));
+        sessions.push(conn.clone());
+
+        Ok(conn)
+    }
+
+    async fn unload_conn(&self, name: &str, id: u64) -> QuipResult<()> {
+        self.unload_cached(name, id).await.map(|_| ())
+    }
+
+    async fn detach_conn(&self, name: &str, id: u64) -> QuipResult<()> {
+        self.detach_cached(name, id).await.map(|_| ())
+    }
 
-                if conn_handle.status != ConnectionStatus::Cache {
-                    return Err(QuipError::Duplicate(format!("User {} exists", name)));
+    async fn resume_conn(&self, token: &str) -> QuipResult<ConnectionRef> {
+        let conns = self.conns.lock().await;
+
+        for sessions in conns.values() {
+            for conn in sessions {
+                {
+                    let conn = conn.lock().await;
+                    if conn.status != ConnectionStatus::Cache
+                        || conn.token.as_deref() != Some(token)
+                    {
+                        continue;
+                    }
                 }
 
+                // Responses cached while the user was offline are taken over
+                // like loading a new session.
+                let offline = presence_of(sessions).await == Presence::Offline;
+
+                let mut conn_handle = conn.lock().await;
                 conn_handle.status = ConnectionStatus::Auth;
-                conn.clone()
+                conn_handle.restored = offline;
+                conn_handle.expire_queue(self.offline_policy.ttl).await;
+                return Ok(conn.clone());
             }
-            None => {
-                let conn = Arc::new(Mutex::new(Connection::new(name, ConnectionStatus::Auth)));
-                conns.insert(name.into(), conn.clone());
-                conn
+        }
+
+        Err(QuipError::NotFound("No connection to resume".into()))
+    }
+
+    async fn expire_conn(&self, name: &str, token: &str) -> QuipResult<()> {
+        let mut conns = self.conns.lock().await;
+
+        let sessions = match conns.get_mut(name) {
+            Some(sessions) => sessions,
+            None => return Ok(()),
+        };
+
+        let mut index = None;
+        for (i, conn) in sessions.iter().enumerate() {
+            let conn = conn.lock().await;
+            if conn.status == ConnectionStatus::Cache && conn.token.as_deref() == Some(token) {
+                index = Some(i);
+                break;
             }
+        }
+
+        // Messages were requeued when the session was detached.
+        if let Some(index) = index {
+            remove_session(sessions, index, self.offline_policy.limit).await;
+        }
+
+        Ok(())
+    }
+
+    async fn create_user(&self, name: &str, password: &str) -> QuipResult<()> {
+        let password = hash_password_blocking(password).await?;
+        let mut data = self.data.write().await;
+
+        if data.users.contains_key(name) {
+            return Err(QuipError::Duplicate(format!("User {} exists", name)));
+        }
+
+        let user = User {
+            name: name.into(),
+            password,
+            role: UserRole::Member,
+            blocks: BTreeMap::new(),
+            banned_until: None,
+            muted_until: None,
         };
+        data.users.insert(name.into(), Arc::new(user));
 
-        Ok(conn)
+        Ok(())
     }
 
-    async fn unload_conn(&self, name: &str) -> QuipResult<()> {
-        let mut conns = self.conns.lock().await;
-        conns.remove(name);
+    async fn update_password(&self, name: &str, old: &str, new: &str) -> QuipResult<()> {
+        let hash = match self.find_user(name).await {
+            Some(user) => user.password,
+            None => return Err(QuipError::NotFound(format!("No user named {}", name))),
+        };
+        if !verify_password_blocking(Some(hash.clone()), old).await {
+            return Err(QuipError::Authorize(format!(
+                "Incorrect password for user {}",
+                name
+            )));
+        }
+
+        let password = hash_password_blocking(new).await?;
+        let mut data = self.data.write().await;
+
+        let old_user = match data.users.get(name) {
+            Some(user) => user.clone(),
+            None => return Err(QuipError::NotFound(format!("No user named {}", name))),
+        };
+
+        // The password may be changed by another session while verifying.
+        if old_user.password != hash {
+            return Err(QuipError::Authorize(format!(
+                "Incorrect password for user {}",
+                name
+            )));
+        }
+
+        let user = User {
+            password,
+            ..(*old_user).clone()
+        };
+        replace_user(&mut data, &old_user, user);
 
         Ok(())
     }
 
-    async fn find_conn(&self, name: &str) -> QuipResult<ConnectionRef> {
+    async fn find_conns(&self, name: &str) -> QuipResult<Vec<ConnectionRef>> {
         let conns = self.conns.lock().await;
         match conns.get(name) {
-            Some(user) => Ok(user.clone()),
-            None => return Err(QuipError::NotFound(format!("No user named {}", name))),
+            Some(sessions) => Ok(sessions.clone()),
+            None => Err(QuipError::NotFound(format!("No user named {}", name))),
         }
     }
 
-    async fn ensure_conn(&self, name: &str) -> QuipResult<ConnectionRef> {
-        if let None = self.data.users.get(name) {
+    async fn ensure_conns(&self, name: &str) -> QuipResult<Vec<ConnectionRef>> {
+        if !self.data.read().await.users.contains_key(name) {
             return Err(QuipError::NotFound(format!("No user named {}", name)));
         }
 
         let mut conns = self.conns.lock().await;
 
-        let conn = match conns.get(name) {
-            Some(conn) => conn.clone(),
-            None => {
-                let conn = Arc::new(Mutex::new(Connection::new(name, ConnectionStatus::Cache)));
-                conns.insert(name.into(), conn.clone());
-                conn
+        let sessions = conns.entry(name.into()).or_insert_with(|| {
+            let conn = Connection::new(name, ConnectionStatus::Cache);
+            vec![Arc::new(Mutex::new(conn))]
+        });
+
+        Ok(sessions.clone())
+    }
+
+    async fn deliver(&self, name: &str, resp: Response) -> QuipResult<()> {
+        self.deliver_cached(name, resp).await.map(|_| ())
+    }
+
+    async fn next_message_id(&self) -> QuipResult<u64> {
+        Ok(self.next_message_id.fetch_add(1, Ordering::Relaxed))
+    }
+
+    async fn record_history(&self, entry: HistoryEntry, hidden: Vec<String>) -> QuipResult<()> {
+        if !self.history_enabled() {
+            return Ok(());
+        }
+
+        let key = conversation_key(&entry.sender, &entry.target);
+        let mut history = self.history.lock().await;
+
+        let messages = history.entry(key).or_default();
+        messages.push_back(RecordedEntry { entry, hidden });
+        self.prune_history(messages);
+
+        Ok(())
+    }
+
+    async fn find_history(
+        &self,
+        name: &str,
+        target: &str,
+        before: Option<u64>,
+        limit: usize,
+    ) -> QuipResult<Vec<HistoryEntry>> {
+        {
+            let data = self.data.read().await;
+            let found = match target.strip_prefix(GROUP_PREFIX) {
+                Some(group) => data
+                    .groups
+                    .get(group)
+                    .is_some_and(|group| group.0.users.iter().any(|user| user == name)),
+                None => data.users.contains_key(target),
+            };
+
+            if !found {
+                return Err(QuipError::NotFound(format!(
+                    "No conversation of {} with {}",
+                    name, target
+                )));
             }
+        }
+
+        let key = conversation_key(name, target);
+        let mut history = self.history.lock().await;
+
+        let messages = match history.get_mut(&key) {
+            Some(messages) => messages,
+            None => return Ok(Vec::new()),
         };
+        self.prune_history(messages);
 
-        Ok(conn)
+        let mut entries: Vec<HistoryEntry> = messages
+            .iter()
+            .rev()
+            .filter(|recorded| before.is_none_or(|before| recorded.entry.id < before))
+            .filter(|recorded| !recorded.hidden.iter().any(|user| user == name))
+            .take(limit)
+            .map(|recorded| recorded.entry.clone())
+            .collect();
+        entries.reverse();
+
+        Ok(entries)
+    }
+
+    async fn online_users(&self) -> QuipResult<Vec<String>> {
+        let conns = self.conns.lock().await;
+        let mut users = Vec::new();
+
+        for (name, sessions) in conns.iter() {
+            if presence_of(sessions).await != Presence::Offline {
+                users.push(name.clone());
+            }
+        }
+
+        users.sort();
+        Ok(users)
+    }
+
+    async fn find_user_info(&self, name: &str) -> QuipResult<UserInfo> {
+        let mut groups: Vec<String> = {
+            let data = self.data.read().await;
+            if !data.users.contains_key(name) {
+                return Err(QuipError::NotFound(format!("No user named {}", name)));
+            }
+
+            data.groups
+                .values()
+                .filter(|group| group.0.users.iter().any(|user| user == name))
+                .map(|group| group.0.name.clone())
+                .collect()
+        };
+        groups.sort();
+
+        let sessions = self.conns.lock().await.get(name).cloned();
+        let presence = match sessions {
+            Some(sessions) => presence_of(&sessions).await,
+            None => Presence::Offline,
+        };
+
+        let last_seen = match presence {
+            Presence::Offline => self.last_seen.lock().await.get(name).copied(),
+            _ => None,
+        };
+
+        Ok(UserInfo {
+            name: name.into(),
+            presence,
+            last_seen,
+            groups,
+        })
+    }
+
+    async fn presence_audience(&self, name: &str) -> QuipResult<Vec<String>> {
+        let mut audience: HashSet<String> = self
+            .data
+            .read()
+            .await
+            .groups
+            .values()
+            .filter(|group| group.0.users.iter().any(|user| user == name))
+            .flat_map(|group| group.0.users.iter().cloned())
+            .collect();
+
+        if let Some(subscribers) = self.subscribers.lock().await.get(name) {
+            audience.extend(subscribers.iter().cloned());
+        }
+        audience.remove(name);
+
+        let mut audience: Vec<String> = audience.into_iter().collect();
+        audience.sort();
+        Ok(audience)
+    }
+
+    async fn subscribe(&self, name: &str, target: &str) -> QuipResult<()> {
+        if !self.data.read().await.users.contains_key(target) {
+            return Err(QuipError::NotFound(format!("No user named {}", target)));
+        }
+
+        let mut subscribers = self.subscribers.lock().await;
+        if !subscribers
+            .entry(target.into())
+            .or_default()
+            .insert(name.into())
+        {
+            return Err(QuipError::Duplicate(format!(
+                "User {} subscribed {}",
+                name, target
+            )));
+        }
+
+        Ok(())
+    }
+
+    async fn unsubscribe(&self, name: &str, target: &str) -> QuipResult<()> {
+        let mut subscribers = self.subscribers.lock().await;
+
+        let removed = match subscribers.get_mut(target) {
+            Some(names) => names.remove(name),
+            None => false,
+        };
+        if !removed {
+            return Err(QuipError::NotFound(format!(
+                "User {} did not subscribe {}",
+                name, target
+            )));
+        }
+
+        Ok(())
+    }
+
+    async fn block(&self, name: &str, target: &str, policy: BlockPolicy) -> QuipResult<()> {
+        let mut data = self.data.write().await;
+
+        if !data.users.contains_key(target) {
+            return Err(QuipError::NotFound(format!("No user named {}", target)));
+        }
+        let old_user = match data.users.get(name) {
+            Some(user) => user.clone(),
+            None => return Err(QuipError::NotFound(format!("No user named {}", name))),
+        };
+
+        let mut user = (*old_user).clone();
+        user.blocks.insert(target.into(), policy);
+        replace_user(&mut data, &old_user, user);
+
+        Ok(())
+    }
+
+    async fn unblock(&self, name: &str, target: &str) -> QuipResult<()> {
+        let mut data = self.data.write().await;
+
+        let old_user = match data.users.get(name) {
+            Some(user) => user.clone(),
+            None => return Err(QuipError::NotFound(format!("No user named {}", name))),
+        };
+
+        let mut user = (*old_user).clone();
+        if user.blocks.remove(target).is_none() {
+            return Err(QuipError::NotFound(format!(
+                "User {} did not block {}",
+                name, target
+            )));
+        }
+        replace_user(&mut data, &old_user, user);
+
+        Ok(())
+    }
+
+    async fn find_blocks(&self, name: &str) -> QuipResult<Vec<BlockEntry>> {
+        match self.data.read().await.users.get(name) {
+            Some(user) => Ok(user
+                .blocks
+                .iter()
+                .map(|(name, policy)| BlockEntry {
+                    name: name.clone(),
+                    policy: *policy,
+                })
+                .collect()),
+            None => Err(QuipError::NotFound(format!("No user named {}", name))),
+        }
+    }
+
+    async fn find_block(&self, name: &str, sender: &str) -> QuipResult<Option<BlockPolicy>> {
+        match self.data.read().await.users.get(name) {
+            Some(user) => Ok(user.blocks.get(sender).copied()),
+            None => Err(QuipError::NotFound(format!("No user named {}", name))),
+        }
+    }
+
+    async fn ban_user(&self, name: &str, until: Option<u64>) -> QuipResult<()> {
+        let mut data = self.data.write().await;
+
+        let old_user = match data.users.get(name) {
+            Some(user) => user.clone(),
+            None => return Err(QuipError::NotFound(format!("No user named {}", name))),
+        };
+
+        let user = User {
+            banned_until: until,
+            ..(*old_user).clone()
+        };
+        replace_user(&mut data, &old_user, user);
+
+        Ok(())
+    }
+
+    async fn mute_user(&self, name: &str, until: Option<u64>) -> QuipResult<()> {
+        let mut data = self.data.write().await;
+
+        let old_user = match data.users.get(name) {
+            Some(user) => user.clone(),
+            None => return Err(QuipError::NotFound(format!("No user named {}", name))),
+        };
+
+        let user = User {
+            muted_until: until,
+            ..(*old_user).clone()
+        };
+        replace_user(&mut data, &old_user, user);
+
+        Ok(())
+    }
+
+    async fn find_mute(&self, name: &str) -> QuipResult<Option<u64>> {
+        match self.data.read().await.users.get(name) {
+            Some(user) if user.is_muted(unix_time()) => Ok(user.muted_until),
+            Some(_) => Ok(None),
+            None => Err(QuipError::NotFound(format!("No user named {}", name))),
+        }
+    }
+
+    async fn close_conns(&self, name: &str, notice: Response) -> QuipResult<usize> {
+        let (online, removed) = {
+            let mut conns = self.conns.lock().await;
+
+            let sessions = match conns.get_mut(name) {
+                Some(sessions) => sessions,
+                None => return Ok(0),
+            };
+
+            // Tokens are cleared at once, so that sessions detached before the
+            // notice is pushed can not be resumed either.
+            let mut online = Vec::new();
+            let mut detached = Vec::new();
+            for conn in sessions.iter() {
+                let mut handle = conn.lock().await;
+                match handle.status {
+                    ConnectionStatus::Auth => {
+                        handle.token = None;
+                        online.push(conn.clone());
+                    }
+                    ConnectionStatus::Cache if handle.token.is_some() => detached.push(handle.id),
+                    _ => {}
+                }
+            }
+
+            let mut removed = 0;
+            for id in detached {
+                let mut index = None;
+                for (i, conn) in sessions.iter().enumerate() {
+                    if conn.lock().await.id == id {
+                        index = Some(i);
+                        break;
+                    }
+                }
+
+                if let Some(index) = index {
+                    remove_session(sessions, index, self.offline_policy.limit).await;
+                    removed += 1;
+                }
+            }
+
+            (online, removed)
+        };
+
+        // Notices are pushed without locking connections of all users.
+        let closed = online.len() + removed;
+        for conn in online {
+            let mut conn = conn.lock().await;
+            if conn.status == ConnectionStatus::Auth {
+                // Write task returns after the notice is written.
+                conn.status = ConnectionStatus::Close;
+                conn.push(notice.clone()).await;
+            }
+        }
+
+        Ok(closed)
+    }
+
+    async fn find_group(&self, name: &str) -> QuipResult<Vec<String>> {
+        match self.data.read().await.groups.get(name) {
+            Some(group) => Ok(group.0.users.clone()),
+            None => Err(QuipError::NotFound(format!("No group named {}", name))),
+        }
+    }
+
+    async fn create_group(&self, owner: &str, name: &str) -> QuipResult<()> {
+        let mut data = self.data.write().await;
+
+        if data.groups.contains_key(name) {
+            return Err(QuipError::Duplicate(format!("Group {} exists", name)));
+        }
+
+        let user = match data.users.get(owner) {
+            Some(user) => user.clone(),
+            None => return Err(QuipError::NotFound(format!("No user named {}", owner))),
+        };
+
+        let group = Group {
+            name: name.into(),
+            owner: Some(owner.into()),
+            users: vec![owner.into()],
+            moderators: Vec::new(),
+        };
+        data.groups
+            .insert(name.into(), QueryGroup(group, HashSet::from([user])));
+
+        Ok(())
+    }
+
+    async fn update_group(
+        &self,
+        operator: &str,
+        group: &str,
+        action: GroupAction<'_>,
+    ) -> QuipResult<()> {
+        let mut data = self.data.write().await;
+
+        // The group should exist even for admins.
+        let allowed = find_group_mut(&mut data, group)?.0.allows(operator, action);
+        let admin = data
+            .users
+            .get(operator)
+            .is_some_and(|user| user.role == UserRole::Admin);
+        if !allowed && !admin {
+            return Err(QuipError::Forbidden(format!(
+                "User {} can not manage group {}",
+                operator, group
+            )));
+        }
+
+        match action {
+            GroupAction::Add(name) => add_group_user(&mut data, group, name),
+            GroupAction::Remove(name) => remove_group_user(&mut data, group, name),
+            GroupAction::Destroy => destroy_group(&mut data, group),
+            GroupAction::SetRole(name, role) => set_group_role(&mut data, group, name, role),
+        }
+    }
+
+    async fn find_user_role(&self, name: &str) -> QuipResult<UserRole> {
+        match self.data.read().await.users.get(name) {
+            Some(user) => Ok(user.role),
+            None => Err(QuipError::NotFound(format!("No user named {}", name))),
+        }
+    }
+}
+
+/// Backend shared by tests, with members Dessera, Scarlet and Remilia, admin
+/// Sakuya, and group Team owned by Dessera and moderated by Scarlet.
+#[cfg(test)]
+pub(crate) fn test_backend() -> MemoryBackend {
+    let users = [
+        ("Dessera", UserRole::Member),
+        ("Scarlet", UserRole::Member),
+        ("Remilia", UserRole::Member),
+        ("Sakuya", UserRole::Admin),
+    ]
+    .into_iter()
+    .map(|(name, role)| User {
+        name: name.into(),
+        password: String::new(),
+        role,
+        blocks: Default::default(),
+        banned_until: None,
+        muted_until: None,
+    })
+    .collect();
+    let group = Group {
+        name: "Team".into(),
+        owner: Some("Dessera".into()),
+        users: vec!["Dessera".into(), "Scarlet".into(), "Remilia".into()],
+        moderators: vec!["Scarlet".into()],
+    };
+
+    MemoryBackend::from_data(BackendData::new(users, vec![group])).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::NoticeLevel;
+
+    #[tokio::test]
+    async fn test_close_conns() {
+        let backend = test_backend();
+        let notice = Response::notice(NoticeLevel::Warn, "Kicked");
+        assert_eq!(
+            backend
+                .close_conns("Dessera", notice.clone())
+                .await
+                .unwrap(),
+            0
+        );
+
+        // A detached session only.
+        let conn = backend.ensure_conns("Dessera").await.unwrap().remove(0);
+        conn.lock().await.token = Some("Token".into());
+        assert_eq!(
+            backend
+                .close_conns("Dessera", notice.clone())
+                .await
+                .unwrap(),
+            1
+        );
+        assert!(backend.resume_conn("Token").await.is_err());
+
+        conn.lock().await.status = ConnectionStatus::Auth;
+        assert_eq!(backend.close_conns("Dessera", notice).await.unwrap(), 1);
+
+        let conn = conn.lock().await;
+        assert_eq!(conn.status, ConnectionStatus::Close);
+        assert_eq!(conn.queue.lock().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_update_group() {
+        let backend = test_backend();
+
+        assert!(matches!(
+            backend
+                .update_group("Scarlet", "Team", GroupAction::Destroy)
+                .await,
+            Err(QuipError::Forbidden(_))
+        ));
+        assert!(matches!(
+            backend
+                .update_group("Remilia", "Team", GroupAction::Add("Sakuya"))
+                .await,
+            Err(QuipError::Forbidden(_))
+        ));
+
+        let action = GroupAction::SetRole("Remilia", GroupRole::Moderator);
+        backend
+            .update_group("Dessera", "Team", action)
+            .await
+            .unwrap();
+        backend
+            .update_group("Remilia", "Team", GroupAction::Add("Sakuya"))
+            .await
+            .unwrap();
+
+        // Roles are checked with the update, so a demoted moderator is denied.
+        let action = GroupAction::SetRole("Remilia", GroupRole::Member);
+        backend
+            .update_group("Sakuya", "Team", action)
+            .await
+            .unwrap();
+        assert!(matches!(
+            backend
+                .update_group("Remilia", "Team", GroupAction::Remove("Sakuya"))
+                .await,
+            Err(QuipError::Forbidden(_))
+        ));
+
+        backend
+            .update_group("Sakuya", "Team", GroupAction::Destroy)
+            .await
+            .unwrap();
+        assert!(matches!(
+            backend
+                .update_group("Sakuya", "Team", GroupAction::Destroy)
+                .await,
+            Err(QuipError::NotFound(_))
+        ));
     }
 }
@@ -1,25 +1,215 @@
+pub mod file;
 pub mod memory;
 
+pub use file::*;
 pub use memory::*;
 
-use crate::{QuipResult, server::connection::ConnectionRef};
+use crate::{
+    QuipResult,
+    data::{GroupAction, UserRole},
+    response::{BlockEntry, BlockPolicy, HistoryEntry, Response, UserInfo},
+    server::connection::ConnectionRef,
+};
 use std::future::Future;
 
 /// Server backend interface, which implements storage of connections.
-pub trait Backend {
-    /// Load a connection in backend.
+///
+/// Backends do not check roles of operators, privileged commands are checked
+/// by the service layer, except [`Backend::update_group`] which checks roles
+/// with the update atomically.
+pub trait Backend: Send + Sync {
+    /// Load a new session of a user in backend, which is
+    /// [`QuipError::Authorize`](crate::QuipError) if the user is unknown or
+    /// the password is incorrect, and
+    /// [`QuipError::Forbidden`](crate::QuipError) if the user is banned.
     fn load_conn(
         &self,
         name: &str,
         password: &str,
     ) -> impl Future<Output = QuipResult<ConnectionRef>> + Send;
 
-    // Unload a connection in backend.
-    fn unload_conn(&self, name: &str) -> impl Future<Output = QuipResult<()>> + Send;
+    /// Unload a session of a user in backend, responses are cached again after
+    /// all sessions are unloaded.
+    fn unload_conn(&self, name: &str, id: u64) -> impl Future<Output = QuipResult<()>> + Send;
 
-    /// Find a connection from backend.
-    fn find_conn(&self, name: &str) -> impl Future<Output = QuipResult<ConnectionRef>> + Send;
+    /// Detach a session whose socket dropped, which caches responses until it
+    /// is resumed or expired.
+    fn detach_conn(&self, name: &str, id: u64) -> impl Future<Output = QuipResult<()>> + Send;
 
-    /// Find or create a connection in backend.
-    fn ensure_conn(&self, name: &str) -> impl Future<Output = QuipResult<ConnectionRef>> + Send;
+    /// Reattach a detached session with its token.
+    fn resume_conn(&self, token: &str) -> impl Future<Output = QuipResult<ConnectionRef>> + Send;
+
+    /// Unload a detached session if it was not resumed since detached with
+    /// the token.
+    fn expire_conn(&self, name: &str, token: &str) -> impl Future<Output = QuipResult<()>> + Send;
+
+    /// Create a user with plaintext password.
+    fn create_user(
+        &self,
+        name: &str,
+        password: &str,
+    ) -> impl Future<Output = QuipResult<()>> + Send;
+
+    /// Update password of a user, the old password should be verified first.
+    fn update_password(
+        &self,
+        name: &str,
+        old: &str,
+        new: &str,
+    ) -> impl Future<Output = QuipResult<()>> + Send;
+
+    /// Find all connections of a user from backend.
+    fn find_conns(&self, name: &str)
+    -> impl Future<Output = QuipResult<Vec<ConnectionRef>>> + Send;
+
+    /// Find all connections of a user, or create a cache connection if there
+    /// is none.
+    fn ensure_conns(
+        &self,
+        name: &str,
+    ) -> impl Future<Output = QuipResult<Vec<ConnectionRef>>> + Send;
+
+    /// Allocate a message ID, which increases monotonically.
+    fn next_message_id(&self) -> impl Future<Output = QuipResult<u64>> + Send;
+
+    /// Deliver a response to all sessions of a user, the response is cached if
+    /// the user is offline, or [`QuipError::QueueFull`](crate::QuipError) if
+    /// the cache is full.
+    fn deliver(&self, name: &str, resp: Response) -> impl Future<Output = QuipResult<()>> + Send {
+        async move {
+            for conn in self.ensure_conns(name).await? {
+                conn.lock().await.push(resp.clone()).await;
+            }
+            Ok(())
+        }
+    }
+
+    /// Confirm responses cached while a user was offline were written to a
+    /// session which took them over, so that they are not restored again.
+    /// This does nothing by default.
+    fn confirm_cached(&self, _name: &str) -> impl Future<Output = QuipResult<()>> + Send {
+        async { Ok(()) }
+    }
+
+    /// Record a message in history of its conversation, which is hidden from
+    /// `hidden` users, i.e. receivers silently blocking the sender.
+    fn record_history(
+        &self,
+        entry: HistoryEntry,
+        hidden: Vec<String>,
+    ) -> impl Future<Output = QuipResult<()>> + Send;
+
+    /// Find at most `limit` latest messages between a user and the target
+    /// user or group, which are older than `before` if provided. Messages are
+    /// sorted by ID, and the user should be a member of the target group.
+    /// Messages hidden from the user when recorded are not included.
+    fn find_history(
+        &self,
+        name: &str,
+        target: &str,
+        before: Option<u64>,
+        limit: usize,
+    ) -> impl Future<Output = QuipResult<Vec<HistoryEntry>>> + Send;
+
+    /// Cache sessions which are still online as if their sockets dropped, so
+    /// that their messages are persisted. This is called before
+    /// [`Backend::persist`] if connections were aborted, and does nothing by
+    /// default.
+    fn cache_conns(&self) -> impl Future<Output = QuipResult<()>> + Send {
+        async { Ok(()) }
+    }
+
+    /// Persist pending state before server exits, which does nothing by
+    /// default.
+    fn persist(&self) -> impl Future<Output = QuipResult<()>> + Send {
+        async { Ok(()) }
+    }
+
+    /// Find names of all online users, sorted by name.
+    fn online_users(&self) -> impl Future<Output = QuipResult<Vec<String>>> + Send;
+
+    /// Find online state, groups and last seen time of a user.
+    fn find_user_info(&self, name: &str) -> impl Future<Output = QuipResult<UserInfo>> + Send;
+
+    /// Find names of users who should be notified when presence of a user
+    /// changes, i.e. users sharing a group with it and its subscribers.
+    fn presence_audience(&self, name: &str)
+    -> impl Future<Output = QuipResult<Vec<String>>> + Send;
+
+    /// Subscribe presence of the target user.
+    fn subscribe(&self, name: &str, target: &str) -> impl Future<Output = QuipResult<()>> + Send;
+
+    /// Unsubscribe presence of the target user.
+    fn unsubscribe(&self, name: &str, target: &str) -> impl Future<Output = QuipResult<()>> + Send;
+
+    /// Block messages from the target user, or update the policy if it was
+    /// blocked.
+    fn block(
+        &self,
+        name: &str,
+        target: &str,
+        policy: BlockPolicy,
+    ) -> impl Future<Output = QuipResult<()>> + Send;
+
+    /// Unblock messages from the target user.
+    fn unblock(&self, name: &str, target: &str) -> impl Future<Output = QuipResult<()>> + Send;
+
+    /// Find users blocked by a user, sorted by name.
+    fn find_blocks(&self, name: &str) -> impl Future<Output = QuipResult<Vec<BlockEntry>>> + Send;
+
+    /// Find the policy of a user for messages from the sender, `None` if the
+    /// sender is not blocked.
+    fn find_block(
+        &self,
+        name: &str,
+        sender: &str,
+    ) -> impl Future<Output = QuipResult<Option<BlockPolicy>>> + Send;
+
+    /// Ban a user until the time in seconds since UNIX epoch or
+    /// [`FOREVER`](crate::data::FOREVER), or lift the ban if `None`. Banned
+    /// users can not load sessions.
+    fn ban_user(
+        &self,
+        name: &str,
+        until: Option<u64>,
+    ) -> impl Future<Output = QuipResult<()>> + Send;
+
+    /// Mute a user until the time like [`Backend::ban_user`], or lift the mute
+    /// if `None`.
+    fn mute_user(
+        &self,
+        name: &str,
+        until: Option<u64>,
+    ) -> impl Future<Output = QuipResult<()>> + Send;
+
+    /// Find when a user is muted until, `None` if the user is not muted now.
+    fn find_mute(&self, name: &str) -> impl Future<Output = QuipResult<Option<u64>>> + Send;
+
+    /// Close all sessions of a user after the notice is written, detached
+    /// sessions can not be resumed either. Returns count of closed sessions,
+    /// including the detached ones.
+    fn close_conns(
+        &self,
+        name: &str,
+        notice: Response,
+    ) -> impl Future<Output = QuipResult<usize>> + Send;
+
+    /// Find names of all users in a group.
+    fn find_group(&self, name: &str) -> impl Future<Output = QuipResult<Vec<String>>> + Send;
+
+    /// Create a group with its owner as the first member.
+    fn create_group(&self, owner: &str, name: &str) -> impl Future<Output = QuipResult<()>> + Send;
+
+    /// Update a group by a user, admins are allowed to update all groups. The
+    /// roles are checked under the same lock as the update, and
+    /// [`QuipError::Forbidden`](crate::QuipError) is returned if not allowed.
+    fn update_group(
+        &self,
+        operator: &str,
+        group: &str,
+        action: GroupAction<'_>,
+    ) -> impl Future<Output = QuipResult<()>> + Send;
+
+    /// Find the server-wide role of a user.
+    fn find_user_role(&self, name: &str) -> impl Future<Output = QuipResult<UserRole>> + Send;
 }
@@ -0,0 +1,167 @@
+//! Server policies.
+
+use serde::Deserialize;
+
+/// Policies of a running server.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// Allow unauthenticated connections to create users with `Register`.
+    pub allow_register: bool,
+
+    /// Minimum length of passwords for `Register` and `Passwd`.
+    pub min_password_len: usize,
+
+    /// Seconds to wait for connections to close when shutting down.
+    pub shutdown_timeout: u64,
+
+    /// Seconds to keep a dropped connection for `Resume`, `0` to disable.
+    pub resume_grace: u64,
+
+    /// Maximum length of request lines in bytes, `0` if unlimited.
+    pub max_line_len: usize,
+
+    /// Seconds for new connections to authenticate, `0` to disable. The
+    /// SSL/TLS handshake before it has the same timeout.
+    pub login_timeout: u64,
+
+    /// Seconds without requests before `Ping`, the connection is closed if
+    /// `Pong` is not received after another period, `0` to disable. Only
+    /// authenticated connections are pinged.
+    pub idle_timeout: u64,
+
+    /// Requests per second of a connection, `0` if unlimited.
+    pub request_rate: u32,
+
+    /// Requests allowed in a burst.
+    pub request_burst: u32,
+
+    /// Messages per second of a connection with `Send`, `0` if unlimited.
+    pub message_rate: u32,
+
+    /// Messages allowed in a burst.
+    pub message_burst: u32,
+
+    /// Connection attempts per second from an IP address, `0` if unlimited.
+    pub connect_rate: u32,
+
+    /// Connection attempts allowed in a burst.
+    pub connect_burst: u32,
+
+    /// Rate limited requests before the connection is closed, `0` to never
+    /// close.
+    pub max_violations: u32,
+
+    /// Maximum messages kept in history of a conversation, `0` to disable
+    /// history.
+    pub history_limit: usize,
+
+    /// Seconds to keep messages in history, `0` to keep them forever.
+    pub history_ttl: u64,
+
+    /// Maximum responses cached for an offline user, `0` if unlimited.
+    pub offline_limit: usize,
+
+    /// Seconds to cache responses for an offline user, `0` to cache them
+    /// forever.
+    pub offline_ttl: u64,
+
+    /// What to do when responses cached for an offline user reach the limit.
+    pub offline_overflow: OfflineOverflow,
+}
+
+/// Policy when responses cached for an offline user reach the limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum OfflineOverflow {
+    /// Reject the new message, the sender receives `QueueFull`.
+    #[default]
+    Reject,
+    /// Drop the oldest cached response for the new one.
+    DropOldest,
+}
+
+/// Limits of responses cached for offline users.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OfflinePolicy {
+    /// Maximum cached responses of a user, `0` if unlimited.
+    pub limit: usize,
+
+    /// Seconds to cache a response, `0` to cache it forever.
+    pub ttl: u64,
+
+    /// What to do when the limit is reached.
+    pub overflow: OfflineOverflow,
+}
+
+/// Retention limits of message history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryRetention {
+    /// Maximum messages kept in a conversation, `0` to disable history.
+    pub limit: usize,
+
+    /// Seconds to keep a message, `0` to keep it forever.
+    pub ttl: u64,
+}
+
+impl Default for HistoryRetention {
+    fn default() -> Self {
+        ServerConfig::default().history_retention()
+    }
+}
+
+impl Default for OfflinePolicy {
+    fn default() -> Self {
+        ServerConfig::default().offline_policy()
+    }
+}
+
+impl ServerConfig {
+    /// Check if a new password is too weak.
+    pub fn is_weak_password(&self, password: &str) -> bool {
+        password.chars().count() < self.min_password_len
+    }
+
+    /// Retention limits of message history.
+    pub fn history_retention(&self) -> HistoryRetention {
+        HistoryRetention {
+            limit: self.history_limit,
+            ttl: self.history_ttl,
+        }
+    }
+
+    /// Limits of responses cached for offline users.
+    pub fn offline_policy(&self) -> OfflinePolicy {
+        OfflinePolicy {
+            limit: self.offline_limit,
+            ttl: self.offline_ttl,
+            overflow: self.offline_overflow,
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            allow_register: false,
+            min_password_len: 8,
+            shutdown_timeout: 10,
+            resume_grace: 60,
+            max_line_len: 4096,
+            login_timeout: 30,
+            idle_timeout: 60,
+            request_rate: 20,
+            request_burst: 40,
+            message_rate: 5,
+            message_burst: 20,
+            connect_rate: 2,
+            connect_burst: 10,
+            max_violations: 20,
+            history_limit: 1000,
+            history_ttl: 0,
+            offline_limit: 1000,
+            offline_ttl: 604800,
+            offline_overflow: OfflineOverflow::Reject,
+        }
+    }
+}
@@ -1,8 +1,19 @@
-use crate::response::Response;
-use std::{collections::VecDeque, sync::Arc};
+use crate::response::{Capability, Presence, Response, ResponseBody};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
 use tokio::sync::{Mutex, Notify};
 
 /// Connection status to cache message before login.
+///
+/// An authenticated connection is `Cache` again after its socket dropped,
+/// until it is resumed or expired.
 #[derive(Debug, PartialEq, Eq)]
 pub enum ConnectionStatus {
     Cache,
@@ -10,13 +21,53 @@ pub enum ConnectionStatus {
     Close,
 }
 
+/// Response in the queue of a connection, with the time it was queued.
+#[derive(Debug, Clone)]
+pub struct QueuedResponse {
+    pub resp: Response,
+    pub time: u64,
+}
+
+impl QueuedResponse {
+    /// Create a [`QueuedResponse`] queued now.
+    pub fn new(resp: Response) -> Self {
+        Self {
+            resp,
+            time: unix_time(),
+        }
+    }
+}
+
+/// Maximum count of unacknowledged messages of a connection, the oldest one
+/// is dropped with a warning if exceeded.
+pub const MAX_UNACKED: usize = 1000;
+
+/// Id of the next connection.
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
 /// Connection handler for server.
+///
+/// A user may own several authenticated connections (sessions) at the same
+/// time, which are distinguished by `id`.
+///
+/// Responses are adapted to `caps` of the client when written. With `ack`,
+/// messages written to client are kept in `unacked` until acknowledged, and
+/// are queued again when the connection is cached.
+///
+/// `restored` is set if the connection took over responses cached while the
+/// user was offline, until they are written to client.
 #[derive(Debug)]
 pub struct Connection {
-    pub queue: Arc<Mutex<VecDeque<Response>>>,
+    pub id: u64,
+    pub queue: Arc<Mutex<VecDeque<QueuedResponse>>>,
+    pub unacked: Arc<Mutex<BTreeMap<u64, Response>>>,
     pub notify: Arc<Notify>,
     pub name: String,
     pub status: ConnectionStatus,
+    pub away: bool,
+    pub token: Option<String>,
+    pub caps: HashSet<Capability>,
+    pub restored: bool,
 }
 
 pub type ConnectionRef = Arc<Mutex<Connection>>;
@@ -24,10 +75,117 @@ pub type ConnectionRef = Arc<Mutex<Connection>>;
 impl Connection {
     pub fn new(name: impl Into<String>, status: ConnectionStatus) -> Self {
         Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
             queue: Arc::new(Mutex::new(VecDeque::new())),
+            unacked: Arc::new(Mutex::new(BTreeMap::new())),
             notify: Arc::new(Notify::new()),
             name: name.into(),
             status,
+            away: false,
+            token: None,
+            caps: HashSet::new(),
+            restored: false,
+        }
+    }
+
+    /// Drop queued responses other than messages, which are replies to
+    /// requests and keepalive pings of the session.
+    pub async fn retain_messages(&self) {
+        self.queue
+            .lock()
+            .await
+            .retain(|queued| matches!(queued.resp.body, ResponseBody::Recv(..)));
+    }
+
+    /// Move unacknowledged messages back to the front of the queue, returns
+    /// the requeued ones.
+    ///
+    /// The queue is limited to `limit` responses like an offline cache unless
+    /// it is `0`, the oldest messages are dropped if there is no room.
+    pub async fn requeue_unacked(&self, limit: usize) -> Vec<Response> {
+        let unacked = std::mem::take(&mut *self.unacked.lock().await);
+
+        let time = unix_time();
+        let mut queue = self.queue.lock().await;
+        let room = match limit {
+            0 => unacked.len(),
+            limit => limit.saturating_sub(queue.len()),
+        };
+
+        let mut requeued: Vec<Response> = unacked.into_values().rev().take(room).collect();
+        for resp in requeued.iter() {
+            queue.push_front(QueuedResponse {
+                resp: resp.clone(),
+                time,
+            });
         }
+
+        requeued.reverse();
+        requeued
     }
+
+    /// Remove queued responses older than `ttl` seconds, which does nothing if
+    /// `ttl` is `0`.
+    pub async fn expire_queue(&self, ttl: u64) {
+        if ttl == 0 {
+            return;
+        }
+
+        let now = unix_time();
+        self.queue
+            .lock()
+            .await
+            .retain(|queued| queued.time + ttl >= now);
+    }
+
+    /// Push a response to the queue, and wake up the write task if the
+    /// connection is not cached.
+    pub async fn push(&self, resp: Response) {
+        self.push_at(resp, unix_time()).await
+    }
+
+    /// Push a response which was queued at `time`.
+    pub async fn push_at(&self, resp: Response, time: u64) {
+        self.queue
+            .lock()
+            .await
+            .push_back(QueuedResponse { resp, time });
+
+        if self.status != ConnectionStatus::Cache {
+            self.notify.notify_one();
+        }
+    }
+}
+
+/// Seconds since the Unix epoch.
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+/// Generate a random token to resume a connection.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Presence of a user with the connections, `Away` if all sessions are away.
+pub async fn presence_of(conns: &[ConnectionRef]) -> Presence {
+    let mut presence = Presence::Offline;
+
+    for conn in conns {
+        let conn = conn.lock().await;
+        if conn.status != ConnectionStatus::Auth {
+            continue;
+        }
+
+        if !conn.away {
+            return Presence::Online;
+        }
+        presence = Presence::Away;
+    }
+
+    presence
 }
@@ -0,0 +1,257 @@
+//! Rate limits of connections and requests.
+
+use crate::{QuipError, QuipResult, request::RequestBody, server::config::ServerConfig};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Count of tracked addresses before idle ones are dropped.
+const MAX_TRACKED_ADDRS: usize = 4096;
+
+/// Quiet period after which violations of a connection are forgotten.
+const VIOLATION_WINDOW: Duration = Duration::from_secs(60);
+
+/// Token bucket, which allows bursts of `burst` operations and refills `rate`
+/// tokens per second. The bucket is unlimited if `rate` is `0`.
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: u32, burst: u32) -> Self {
+        let burst = f64::from(burst.max(1));
+
+        Self {
+            rate: f64::from(rate),
+            burst,
+            tokens: burst,
+            last: Instant::now(),
+        }
+    }
+
+    /// Take a token, `false` if the bucket is empty.
+    pub fn try_take(&mut self) -> bool {
+        if self.rate == 0.0 {
+            return true;
+        }
+
+        self.refill();
+        if self.tokens < 1.0 {
+            return false;
+        }
+
+        self.tokens -= 1.0;
+        true
+    }
+
+    /// Check if the bucket is refilled, which is the same as a new one.
+    pub fn is_full(&mut self) -> bool {
+        self.refill();
+        self.tokens >= self.burst
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.last = now;
+    }
+}
+
+/// Limits of connection attempts per source IP address, used by listeners.
+#[derive(Debug)]
+pub struct IpLimiter {
+    rate: u32,
+    burst: u32,
+    buckets: Mutex<HashMap<IpAddr, TokenBucket>>,
+}
+
+impl IpLimiter {
+    /// Create a [`IpLimiter`], which is unlimited if `rate` is `0`.
+    pub fn new(rate: u32, burst: u32) -> Self {
+        Self {
+            rate,
+            burst,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record a connection attempt, [`QuipError::RateLimited`] if there are
+    /// too many attempts from the address.
+    pub fn check(&self, addr: IpAddr) -> QuipResult<()> {
+        if self.rate == 0 {
+            return Ok(());
+        }
+
+        let mut buckets = self
+            .buckets
+            .lock()
+            .map_err(|_| QuipError::Unknown("Rate limiter was poisoned".into()))?;
+
+        if buckets.len() >= MAX_TRACKED_ADDRS {
+            buckets.retain(|_, bucket| !bucket.is_full());
+        }
+
+        let bucket = buckets
+            .entry(addr)
+            .or_insert_with(|| TokenBucket::new(self.rate, self.burst));
+        match bucket.try_take() {
+            true => Ok(()),
+            false => Err(QuipError::RateLimited(format!(
+                "Too many connections from {}",
+                addr
+            ))),
+        }
+    }
+}
+
+impl Default for IpLimiter {
+    fn default() -> Self {
+        Self::new(0, 0)
+    }
+}
+
+/// Limits of requests and messages in a connection.
+///
+/// Requests exceeding the limits are violations, and the connection should
+/// be closed after too many violations. Violations are reset once the
+/// connection stays within the limits for [`VIOLATION_WINDOW`].
+#[derive(Debug)]
+pub struct SessionLimiter {
+    requests: TokenBucket,
+    messages: TokenBucket,
+    violations: u32,
+    last_violation: Instant,
+    max_violations: u32,
+}
+
+impl SessionLimiter {
+    pub fn new(config: &ServerConfig) -> Self {
+        Self {
+            requests: TokenBucket::new(config.request_rate, config.request_burst),
+            messages: TokenBucket::new(config.message_rate, config.message_burst),
+            violations: 0,
+            last_violation: Instant::now(),
+            max_violations: config.max_violations,
+        }
+    }
+
+    /// Check if a request is allowed, or [`QuipError::Disconnect`] after too
+    /// many violations. `Ack` and `Pong` are answers to the server, which are
+    /// never limited.
+    pub fn check(&mut self, body: &RequestBody) -> QuipResult<bool> {
+        let allowed = match body {
+            RequestBody::Ack(_) | RequestBody::Pong(_) => true,
+            RequestBody::Send(..) => self.requests.try_take() && self.messages.try_take(),
+            _ => self.requests.try_take(),
+        };
+
+        if !allowed {
+            let now = Instant::now();
+            if now.duration_since(self.last_violation) >= VIOLATION_WINDOW {
+                self.violations = 0;
+            }
+
+            self.last_violation = now;
+            self.violations += 1;
+            if self.max_violations > 0 && self.violations >= self.max_violations {
+                return Err(QuipError::Disconnect);
+            }
+        }
+
+        Ok(allowed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_token_bucket() {
+        let mut bucket = TokenBucket::new(1, 3);
+        assert!((0..3).all(|_| bucket.try_take()));
+        assert!(!bucket.try_take());
+        assert!(!bucket.is_full());
+
+        let mut bucket = TokenBucket::new(0, 0);
+        assert!((0..100).all(|_| bucket.try_take()));
+    }
+
+    #[test]
+    fn test_ip_limiter() {
+        let limiter = IpLimiter::new(1, 2);
+        let addr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        assert!(limiter.check(addr).is_ok());
+        assert!(limiter.check(addr).is_ok());
+        assert!(matches!(
+            limiter.check(addr),
+            Err(QuipError::RateLimited(_))
+        ));
+
+        let other = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        assert!(limiter.check(other).is_ok());
+    }
+
+    #[test]
+    fn test_session_limiter() {
+        let config = ServerConfig {
+            message_rate: 1,
+            message_burst: 1,
+            max_violations: 2,
+            ..ServerConfig::default()
+        };
+        let mut limiter = SessionLimiter::new(&config);
+        let send = RequestBody::Send("Dessera".into(), "Hello".into(), false);
+
+        assert!(limiter.check(&send).unwrap());
+        assert!(!limiter.check(&send).unwrap());
+        assert!(limiter.check(&RequestBody::Nop).unwrap());
+        assert!(matches!(limiter.check(&send), Err(QuipError::Disconnect)));
+    }
+
+    #[test]
+    fn test_session_limiter_exempt() {
+        let config = ServerConfig {
+            request_rate: 1,
+            request_burst: 1,
+            max_violations: 1,
+            ..ServerConfig::default()
+        };
+        let mut limiter = SessionLimiter::new(&config);
+
+        assert!(limiter.check(&RequestBody::Nop).unwrap());
+        assert!((0..100).all(|id| limiter.check(&RequestBody::Ack(id)).unwrap()));
+        assert!(limiter.check(&RequestBody::Pong("Token".into())).unwrap());
+    }
+
+    #[test]
+    fn test_session_limiter_decay() {
+        let config = ServerConfig {
+            request_rate: 1,
+            request_burst: 1,
+            max_violations: 2,
+            ..ServerConfig::default()
+        };
+        let mut limiter = SessionLimiter::new(&config);
+
+        assert!(limiter.check(&RequestBody::Nop).unwrap());
+        assert!(!limiter.check(&RequestBody::Nop).unwrap());
+
+        limiter.last_violation -= VIOLATION_WINDOW;
+        assert!(!limiter.check(&RequestBody::Nop).unwrap());
+        assert!(matches!(
+            limiter.check(&RequestBody::Nop),
+            Err(QuipError::Disconnect)
+        ));
+    }
+}
@@ -7,9 +7,16 @@ pub use tcp::*;
 pub use tls::*;
 
 use crate::{QuipResult, io::DynamicQuipIO};
+use std::pin::Pin;
+
+/// Handshake of an accepted connection, which resolves to its IO.
+pub type Handshake = Pin<Box<dyn Future<Output = QuipResult<DynamicQuipIO>> + Send>>;
 
 /// Server listener interface.
 pub trait Listener {
     /// Accept a connection from listener.
-    fn accept(&self) -> impl Future<Output = QuipResult<DynamicQuipIO>> + Send;
+    ///
+    /// The handshake is returned instead of performed, so that it is awaited
+    /// in the task of the connection and slow clients do not block accepting.
+    fn accept(&self) -> impl Future<Output = QuipResult<Handshake>> + Send;
 }
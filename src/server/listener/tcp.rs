@@ -3,7 +3,10 @@
 use crate::{
     QuipResult,
     io::{DynamicQuipIO, tcp::QuipTcpStream},
-    server::listener::Listener,
+    server::{
+        limit::IpLimiter,
+        listener::{Handshake, Listener},
+    },
 };
 use log::info;
 use tokio::net::{TcpListener as TokioTcpListener, ToSocketAddrs};
@@ -11,6 +14,7 @@ use tokio::net::{TcpListener as TokioTcpListener, ToSocketAddrs};
 /// Wrapper for [`TcpListener`].
 pub struct TcpListener {
     listener: TokioTcpListener,
+    limiter: IpLimiter,
 }
 
 impl TcpListener {
@@ -22,15 +26,26 @@ impl TcpListener {
             info!("Tcp listener was binded to {}", local_addr);
         }
 
-        Ok(Self { listener })
+        Ok(Self {
+            listener,
+            limiter: IpLimiter::default(),
+        })
+    }
+
+    /// Limit connection attempts per second from an IP address.
+    pub fn with_rate_limit(mut self, rate: u32, burst: u32) -> Self {
+        self.limiter = IpLimiter::new(rate, burst);
+        self
     }
 }
 
 impl Listener for TcpListener {
-    async fn accept(&self) -> QuipResult<DynamicQuipIO> {
+    async fn accept(&self) -> QuipResult<Handshake> {
         let (socket, addr) = self.listener.accept().await?;
+        self.limiter.check(addr.ip())?;
         info!("Tcp socket {} accepted", addr);
 
-        Ok(Box::new(QuipTcpStream::new(socket)))
+        let io: DynamicQuipIO = Box::new(QuipTcpStream::new(socket));
+        Ok(Box::pin(std::future::ready(Ok(io))))
     }
 }
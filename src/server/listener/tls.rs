@@ -2,20 +2,29 @@
 
 use crate::{
     QuipResult,
-    io::{DynamicQuipIO, tls::QuipTlsStream},
-    server::listener::Listener,
+    io::{
+        DynamicQuipIO,
+        tls::{QuipTlsDuplexStream, QuipTlsStream},
+    },
+    server::{
+        limit::IpLimiter,
+        listener::{Handshake, Listener},
+    },
 };
 use log::info;
 use native_tls::{Identity, TlsAcceptor as NativeTlsAcceptor};
 use std::net::SocketAddr;
 use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
-use tokio_native_tls::{TlsAcceptor, TlsStream};
+use tokio_native_tls::TlsAcceptor;
 
-/// Wrapper of [`TlsAcceptor`].
+/// Wrapper of [`TlsAcceptor`], which accepts two sockets for a connection.
+///
+/// Prefer [`TlsDuplexListener`] unless clients require two sockets.
 pub struct TlsListener {
     rx_listener: TcpListener,
     tx_listener: TcpListener,
     acceptor: TlsAcceptor,
+    limiter: IpLimiter,
 }
 
 impl TlsListener {
@@ -38,30 +47,91 @@ impl TlsListener {
             rx_listener,
             tx_listener,
             acceptor: TlsAcceptor::from(NativeTlsAcceptor::builder(identity).build()?),
+            limiter: IpLimiter::default(),
         })
     }
 
-    pub(self) async fn accept_tls(
+    /// Limit connection attempts per second from an IP address, each socket
+    /// is an attempt.
+    pub fn with_rate_limit(mut self, rate: u32, burst: u32) -> Self {
+        self.limiter = IpLimiter::new(rate, burst);
+        self
+    }
+
+    pub(self) async fn accept_tcp(
         &self,
         listener: &TcpListener,
-    ) -> QuipResult<(TlsStream<TcpStream>, SocketAddr)> {
+    ) -> QuipResult<(TcpStream, SocketAddr)> {
         let (socket, addr) = listener.accept().await?;
-        Ok((self.acceptor.accept(socket).await?, addr))
+        self.limiter.check(addr.ip())?;
+        Ok((socket, addr))
     }
 }
 
 impl Listener for TlsListener {
-    async fn accept(&self) -> QuipResult<DynamicQuipIO> {
+    async fn accept(&self) -> QuipResult<Handshake> {
         let ((rx, rx_addr), (tx, tx_addr)) = tokio::try_join!(
-            self.accept_tls(&self.rx_listener),
-            self.accept_tls(&self.tx_listener)
+            self.accept_tcp(&self.rx_listener),
+            self.accept_tcp(&self.tx_listener)
         )?;
 
-        info!(
-            "SSL/TLS socket accepted, {} [read], {} [write]",
-            rx_addr, tx_addr
-        );
+        let acceptor = self.acceptor.clone();
+        Ok(Box::pin(async move {
+            let (rx, tx) = tokio::try_join!(acceptor.accept(rx), acceptor.accept(tx))?;
+
+            info!(
+                "SSL/TLS socket accepted, {} [read], {} [write]",
+                rx_addr, tx_addr
+            );
+
+            let io: DynamicQuipIO = Box::new(QuipTlsStream::new(rx, tx));
+            Ok(io)
+        }))
+    }
+}
+
+/// Wrapper of [`TlsAcceptor`], which accepts one socket for a connection.
+pub struct TlsDuplexListener {
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    limiter: IpLimiter,
+}
+
+impl TlsDuplexListener {
+    pub async fn bind(addr: impl ToSocketAddrs, identity: Identity) -> QuipResult<Self> {
+        let listener = TcpListener::bind(addr).await?;
+
+        if let Ok(addr) = listener.local_addr() {
+            info!("SSL/TLS listener was binded to {}", addr);
+        }
+
+        Ok(Self {
+            listener,
+            acceptor: TlsAcceptor::from(NativeTlsAcceptor::builder(identity).build()?),
+            limiter: IpLimiter::default(),
+        })
+    }
+
+    /// Limit connection attempts per second from an IP address.
+    pub fn with_rate_limit(mut self, rate: u32, burst: u32) -> Self {
+        self.limiter = IpLimiter::new(rate, burst);
+        self
+    }
+}
+
+impl Listener for TlsDuplexListener {
+    async fn accept(&self) -> QuipResult<Handshake> {
+        let (socket, addr) = self.listener.accept().await?;
+        self.limiter.check(addr.ip())?;
+
+        let acceptor = self.acceptor.clone();
+        Ok(Box::pin(async move {
+            let stream = acceptor.accept(socket).await?;
+
+            info!("SSL/TLS socket {} accepted", addr);
 
-        Ok(Box::new(QuipTlsStream::new(rx, tx)))
+            let io: DynamicQuipIO = Box::new(QuipTlsDuplexStream::new(stream));
+            Ok(io)
+        }))
     }
 }
@@ -1,36 +1,103 @@
 pub mod backend;
+pub mod config;
 pub mod connection;
+pub mod limit;
 pub mod listener;
 pub mod service;
+pub mod shutdown;
 
 use crate::{
-    QuipResult,
-    server::{backend::Backend, listener::Listener},
+    QuipError, QuipResult,
+    io::DynamicQuipIO,
+    server::{
+        backend::Backend,
+        config::ServerConfig,
+        listener::{Handshake, Listener},
+        shutdown::Shutdown,
+    },
 };
-use log::warn;
-use std::sync::Arc;
+use log::{info, warn};
+use std::{sync::Arc, time::Duration};
+use tokio::task::JoinSet;
 
 /// Server runner with any listener and backend implementation.
-pub async fn run<L, B>(listener: L, backend: B) -> QuipResult<()>
+///
+/// The server stops accepting connections when `shutdown` is triggered, then
+/// waits for connections to flush their queues until the shutdown timeout,
+/// and persists the backend before returning.
+pub async fn run<L, B>(
+    listener: L,
+    backend: B,
+    config: ServerConfig,
+    shutdown: Shutdown,
+) -> QuipResult<()>
 where
     L: Listener,
     B: Backend + Send + Sync + 'static,
 {
     let backend = Arc::new(backend);
-    let mut handles = Vec::new();
+    let config = Arc::new(config);
+    let mut handles = JoinSet::new();
     loop {
-        let conn = match listener.accept().await {
-            Ok(conn) => conn,
-            Err(_) => continue,
+        let conn = tokio::select! {
+            conn = listener.accept() => match conn {
+                Ok(conn) => conn,
+                Err(err @ QuipError::RateLimited(_)) => {
+                    warn!("{}", err);
+                    continue;
+                }
+                Err(_) => continue,
+            },
+            _ = shutdown.wait() => break,
         };
 
+        // Drop handles of finished connections.
+        while handles.try_join_next().is_some() {}
+
         let backend = backend.clone();
-        let handle = tokio::spawn(async move {
-            if let Err(err) = service::serve(&*backend, conn).await {
+        let config = config.clone();
+        let shutdown = shutdown.clone();
+        handles.spawn(async move {
+            let conn = match handshake(conn, config.login_timeout).await {
+                Ok(conn) => conn,
+                Err(err) => {
+                    warn!("Handshake failed: {}", err);
+                    return;
+                }
+            };
+            if let Err(err) = service::serve(&*backend, &config, &shutdown, conn).await {
                 warn!("Connection handler exit with error:\n  {}", err);
             }
         });
+    }
+
+    info!(
+        "Server is shutting down, {} connections left",
+        handles.len()
+    );
+
+    let deadline = Duration::from_secs(config.shutdown_timeout);
+    let drain = async { while handles.join_next().await.is_some() {} };
+    if tokio::time::timeout(deadline, drain).await.is_err() {
+        warn!("Abort {} connections after shutdown timeout", handles.len());
+        handles.shutdown().await;
+
+        // Aborted connections did not unload their sessions.
+        backend.cache_conns().await?;
+    }
+
+    backend.persist().await
+}
+
+/// Finish the handshake of a connection in `timeout` seconds, which is
+/// unlimited if `0`.
+async fn handshake(handshake: Handshake, timeout: u64) -> QuipResult<DynamicQuipIO> {
+    if timeout == 0 {
+        return handshake.await;
+    }
 
-        handles.push(handle);
+    match tokio::time::timeout(Duration::from_secs(timeout), handshake).await {
+        Ok(res) => res,
+        Err(_) => Err(std::io::Error::from(std::io::ErrorKind::TimedOut).into()),
     }
 }
@@ -0,0 +1,31 @@
+//! Access control of privileged commands.
+
+use crate::{QuipError, QuipResult, data::UserRole, server::backend::Backend};
+
+/// Check if a user is an admin, [`QuipError::Forbidden`] is returned if not.
+pub async fn check_admin<S: Backend>(server: &S, operator: &str) -> QuipResult<()> {
+    match server.find_user_role(operator).await? {
+        UserRole::Admin => Ok(()),
+        UserRole::Member => Err(QuipError::Forbidden(format!(
+            "User {} is not an admin",
+            operator
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::backend::memory::test_backend;
+
+    #[tokio::test]
+    async fn test_check_admin() {
+        let backend = test_backend();
+
+        assert!(check_admin(&backend, "Sakuya").await.is_ok());
+        assert!(matches!(
+            check_admin(&backend, "Dessera").await,
+            Err(QuipError::Forbidden(_))
+        ));
+    }
+}
@@ -1,56 +1,153 @@
 use crate::{
     QuipError, QuipResult,
+    data::GroupAction,
     io::{
         QuipInput, QuipOutput,
         buffer::{QuipBufReader, QuipBufWriter},
     },
-    request::RequestBody,
-    response::{Response, ResponseBody, ResponseError},
+    request::{GROUP_PREFIX, RequestBody},
+    response::{
+        BlockPolicy, Capability, HistoryEntry, Presence, Response, ResponseBody, ResponseError,
+        encode_list,
+    },
     server::{
         backend::Backend,
-        connection::{ConnectionRef, ConnectionStatus},
+        config::ServerConfig,
+        connection::{ConnectionRef, ConnectionStatus, MAX_UNACKED, QueuedResponse},
+        limit::SessionLimiter,
+        service::{
+            keepalive::Keepalive,
+            moderation::{serve_announce, serve_ban, serve_kick, serve_lift, serve_mute},
+            negotiate, response_body, response_error, shutdown_notice, update_presence,
+            user_presence,
+        },
+        shutdown::Shutdown,
     },
 };
 use log::{debug, warn};
+use std::{collections::HashSet, sync::Arc};
+
+/// Count of messages returned by `History` if no limit is provided.
+const DEFAULT_HISTORY_LIMIT: usize = 50;
+
+/// Maximum count of messages returned by `History`.
+const MAX_HISTORY_LIMIT: usize = 200;
 
 /// Write task for a connection.
 ///
 /// All responses should be written in this, otherwise client may not be able
 /// to process the response correctly.
+///
+/// Responses are adapted to capabilities of the client. Messages are kept as
+/// unacknowledged before written if the client has `ack`, so that they are not
+/// lost if the socket drops. At most [`MAX_UNACKED`] ones are kept, and the
+/// oldest one is dropped with a warning beyond it. Responses cached while the
+/// user was offline are confirmed to the backend after written.
+///
+/// The queue is flushed when shutting down, followed by a notice. The task
+/// also returns after the queue is flushed if the session was closed by the
+/// backend, e.g. kicked.
 pub async fn serve_write<S: Backend, W: QuipOutput>(
-    _server: &S,
+    server: &S,
     conn: ConnectionRef,
     writer: &mut QuipBufWriter<W>,
+    shutdown: &Shutdown,
 ) -> QuipResult<()> {
-    let (notify, queue, name) = {
+    let (notify, queue, unacked, name) = {
         let conn = conn.lock().await;
-        (conn.notify.clone(), conn.queue.clone(), conn.name.clone())
+        (
+            conn.notify.clone(),
+            conn.queue.clone(),
+            conn.unacked.clone(),
+            conn.name.clone(),
+        )
     };
 
     loop {
-        notify.notified().await;
+        let closing = tokio::select! {
+            _ = notify.notified() => false,
+            _ = shutdown.wait() => true,
+        };
 
-        let mut queue = queue.lock().await;
-        let mut cnt: usize = 0;
-        while !queue.is_empty() {
-            let resp = match queue.pop_front() {
+        // Capabilities may change with `Cap`, connection is always locked
+        // before its queue.
+        let (caps, closed, restored) = {
+            let mut conn = conn.lock().await;
+            (
+                conn.caps.clone(),
+                conn.status == ConnectionStatus::Close,
+                std::mem::take(&mut conn.restored),
+            )
+        };
+
+        // The queue is not locked when writing, so that a slow client does
+        // not block others pushing to it.
+        let mut batch = std::mem::take(&mut *queue.lock().await);
+        let cnt = batch.len();
+        while let Some(QueuedResponse { resp, .. }) = batch.pop_front() {
+            if let Some(id) = resp.message_id()
+                && caps.contains(&Capability::Ack)
+            {
+                let mut unacked = unacked.lock().await;
+                unacked.insert(id, resp.clone());
+                if unacked.len() > MAX_UNACKED
+                    && let Some((id, _)) = unacked.pop_first()
+                {
+                    warn!("{}: message {} was not acknowledged, dropped", name, id);
+                }
+            }
+
+            let resp = match adapt_response(resp, &caps) {
                 Some(resp) => resp,
                 None => continue,
             };
+            if let Err(err) = writer.write_response(resp).await {
+                // Responses not written yet are kept for resume.
+                {
+                    let mut queue = queue.lock().await;
+                    for queued in batch.into_iter().rev() {
+                        queue.push_front(queued);
+                    }
+                }
+                conn.lock().await.restored |= restored;
+                return Err(err);
+            }
+        }
 
-            writer.write_response(resp).await?;
-            cnt += 1;
+        if restored {
+            server.confirm_cached(&name).await?;
         }
 
         debug!("Sync {} message to user {}", cnt, name);
+
+        if closing {
+            return writer.write_response(shutdown_notice()).await;
+        }
+        if closed {
+            return Ok(());
+        }
     }
 }
 
+/// Adapt a response to capabilities of the client, `None` if the client can
+/// not process it.
+fn adapt_response(mut resp: Response, caps: &HashSet<Capability>) -> Option<Response> {
+    match &mut resp.body {
+        ResponseBody::Recv(_, _, id) if !caps.contains(&Capability::Ids) => *id = None,
+        ResponseBody::Presence(_, _) if !caps.contains(&Capability::Presence) => return None,
+        ResponseBody::Sent(_, _) if !caps.contains(&Capability::Echo) => return None,
+        _ => {}
+    }
+
+    Some(resp)
+}
+
 /// Read task for a connection.
 ///
 /// This task reads and parse all requests and push responses to write task.
 pub async fn serve_read<S: Backend, R: QuipInput>(
     server: &S,
+    config: &ServerConfig,
     conn: ConnectionRef,
     reader: &mut QuipBufReader<R>,
 ) -> QuipResult<()> {
@@ -59,19 +156,132 @@ pub async fn serve_read<S: Backend, R: QuipInput>(
         (conn.notify.clone(), conn.queue.clone(), conn.name.clone())
     };
 
+    let mut keepalive = Keepalive::new(config);
+    let mut limiter = SessionLimiter::new(config);
+
     loop {
-        let resp = match reader.read_request().await {
+        let request = tokio::select! {
+            request = reader.read_request() => request,
+            _ = keepalive.idle() => {
+                let ping = keepalive
+                    .ping()
+                    .inspect_err(|_| warn!("{}: keepalive timed out", name))?;
+                queue.lock().await.push_back(QueuedResponse::new(ping));
+                notify.notify_one();
+                continue;
+            }
+        };
+
+        let resp = match request {
             Ok(request) => {
                 let body = match request.body {
-                    RequestBody::Send(name, msg) => serve_send(server, &conn, name, msg).await?,
-                    RequestBody::Login(_, _) => ResponseBody::Error(ResponseError::Authorized),
-                    RequestBody::Logout => return Err(QuipError::Disconnect),
+                    _ if !check_limit(&mut limiter, &conn, &request.body).await? => {
+                        ResponseBody::Error(ResponseError::RateLimited)
+                    }
+                    RequestBody::Send(name, msg, online) => {
+                        serve_send(server, &conn, name, msg, online).await?
+                    }
+                    RequestBody::Login(_, _)
+                    | RequestBody::Resume(_)
+                    | RequestBody::Register(_, _) => ResponseBody::Error(ResponseError::Authorized),
+                    RequestBody::Passwd(old, new) => {
+                        serve_passwd(server, config, &name, old, new).await?
+                    }
+                    RequestBody::Logout => {
+                        // Session can not be resumed after logout.
+                        conn.lock().await.token = None;
+                        return Err(QuipError::Disconnect);
+                    }
                     RequestBody::Nop => ResponseBody::Success(None),
+                    RequestBody::GroupCreate(group) => {
+                        serve_group_create(server, &name, group).await?
+                    }
+                    RequestBody::GroupAdd(group, user) => {
+                        let res = server
+                            .update_group(&name, &group, GroupAction::Add(&user))
+                            .await;
+                        response_body(res, user)?
+                    }
+                    RequestBody::GroupRemove(group, user) => {
+                        let res = server
+                            .update_group(&name, &group, GroupAction::Remove(&user))
+                            .await;
+                        response_body(res, user)?
+                    }
+                    RequestBody::GroupDestroy(group) => {
+                        let res = server
+                            .update_group(&name, &group, GroupAction::Destroy)
+                            .await;
+                        response_body(res, group)?
+                    }
+                    RequestBody::GroupRole(group, user, role) => {
+                        let res = server
+                            .update_group(&name, &group, GroupAction::SetRole(&user, role))
+                            .await;
+                        response_body(res, user)?
+                    }
+                    RequestBody::Who(group) => serve_who(server, &name, group).await?,
+                    RequestBody::Whois(user) => match server.find_user_info(&user).await {
+                        Ok(info) => ResponseBody::Success(Some(info.to_string())),
+                        Err(err) => ResponseBody::Error(response_error(err)?),
+                    },
+                    RequestBody::Presence(presence) => {
+                        serve_presence(server, &conn, presence).await
+                    }
+                    RequestBody::Subscribe(user) => {
+                        let res = server.subscribe(&name, &user).await;
+                        response_body(res, user)?
+                    }
+                    RequestBody::Unsubscribe(user) => {
+                        let res = server.unsubscribe(&name, &user).await;
+                        response_body(res, user)?
+                    }
+                    RequestBody::Ack(id) => serve_ack(&conn, id).await,
+                    RequestBody::Cap(requested) => {
+                        let (caps, body) = negotiate(requested);
+                        conn.lock().await.caps = caps;
+                        body
+                    }
+                    RequestBody::Pong(token) => keepalive.pong(&token),
+                    RequestBody::History(target, before, limit) => {
+                        serve_history(server, &name, target, before, limit).await?
+                    }
+                    RequestBody::Block(user, policy) => {
+                        let res = server.block(&name, &user, policy).await;
+                        response_body(res, user)?
+                    }
+                    RequestBody::Unblock(user) => {
+                        let res = server.unblock(&name, &user).await;
+                        response_body(res, user)?
+                    }
+                    RequestBody::Kick(user, reason) => {
+                        serve_kick(server, &name, user, reason).await?
+                    }
+                    RequestBody::Ban(user, duration) => {
+                        serve_ban(server, &name, user, duration).await?
+                    }
+                    RequestBody::Unban(user) => serve_lift(server, &name, user, true).await?,
+                    RequestBody::Mute(user, duration) => {
+                        serve_mute(server, &name, user, duration).await?
+                    }
+                    RequestBody::Unmute(user) => serve_lift(server, &name, user, false).await?,
+                    RequestBody::Announce(msg) => serve_announce(server, &name, msg).await?,
+                    RequestBody::Blocks => match server.find_blocks(&name).await {
+                        Ok(blocks) => {
+                            let items: Vec<String> = blocks.iter().map(|b| b.to_string()).collect();
+                            ResponseBody::Success(encode_list(&items))
+                        }
+                        Err(err) => ResponseBody::Error(response_error(err)?),
+                    },
                 };
 
                 debug!("{}: {}", name, request.tag);
                 Response::new(Some(request.tag), body)
             }
+            Err(err @ QuipError::TooLong(_)) => {
+                warn!("{}: {}", name, err);
+                Response::error(None, ResponseError::TooLong)
+            }
             Err(QuipError::Parse(msg)) => {
                 warn!("{}: {}", name, msg);
                 Response::error(None, ResponseError::BadCommand)
@@ -79,39 +289,479 @@ pub async fn serve_read<S: Backend, R: QuipInput>(
             Err(err) => return Err(err),
         };
 
-        queue.lock().await.push_back(resp);
+        queue.lock().await.push_back(QueuedResponse::new(resp));
         notify.notify_one();
     }
 }
 
-/// Serve `Send` command.
+/// Check rate limits of a request, the session can not be resumed if it is
+/// closed for too many violations.
+async fn check_limit(
+    limiter: &mut SessionLimiter,
+    conn: &ConnectionRef,
+    body: &RequestBody,
+) -> QuipResult<bool> {
+    match limiter.check(body) {
+        Err(err) => {
+            let mut conn = conn.lock().await;
+            warn!("{}: too many rate limited requests", conn.name);
+            conn.token = None;
+            Err(err)
+        }
+        res => res,
+    }
+}
+
+/// Serve `Send` command, the message is not cached for offline users if
+/// `online` is set.
 async fn serve_send<S: Backend>(
     server: &S,
     conn: &ConnectionRef,
     receiver: String,
     msg: String,
+    online: bool,
 ) -> QuipResult<ResponseBody> {
     let sender = {
         let conn = conn.lock().await;
         conn.name.clone()
     };
 
-    let recv_conn = match server.ensure_conn(&receiver).await {
-        Ok(target) => target,
-        Err(_) => return Ok(ResponseBody::Error(ResponseError::NotFound)),
+    if let Ok(Some(_)) = server.find_mute(&sender).await {
+        return Ok(ResponseBody::Error(ResponseError::Forbidden));
+    }
+
+    let id = server.next_message_id().await?;
+    let mut hidden = Vec::new();
+    let is_group = receiver.starts_with(GROUP_PREFIX);
+    let body = match receiver.strip_prefix(GROUP_PREFIX) {
+        Some(group) => {
+            let msg = msg.clone();
+            serve_send_group(server, id, &sender, group, msg, online, &mut hidden).await
+        }
+        None => match server.find_block(&receiver, &sender).await {
+            // The sender should not learn about silent blocks, so the message
+            // is recorded as sent but hidden from the receiver.
+            Ok(Some(BlockPolicy::Silent)) => {
+                hidden.push(receiver.clone());
+                None
+            }
+            Ok(Some(BlockPolicy::Reject)) => Some(ResponseError::Blocked),
+            _ => serve_send_user(server, id, &sender, &receiver, msg.clone(), online).await,
+        },
+    };
+
+    match body {
+        // Group messages rejected by some members are still delivered to the
+        // others, so they are recorded before the sender learns about it.
+        Some(ResponseError::QueueFull) if is_group => {}
+        Some(err) => return Ok(ResponseBody::Error(err)),
+        None => {}
+    }
+
+    let entry = HistoryEntry::new(id, &sender, &receiver, &msg);
+    if let Err(err) = server.record_history(entry, hidden).await {
+        warn!("Failed to record message {} in history: {}", id, err);
+    }
+
+    // Other sessions of the sender should see the message too.
+    for session in server.find_conns(&sender).await? {
+        if Arc::ptr_eq(&session, conn) {
+            continue;
+        }
+
+        let session = session.lock().await;
+        if session.status == ConnectionStatus::Auth {
+            session.push(Response::sent(&receiver, &msg)).await;
+        }
+    }
+
+    match body {
+        Some(err) => Ok(ResponseBody::Error(err)),
+        None => Ok(ResponseBody::Success(Some(receiver))),
+    }
+}
+
+/// Send message to a single user.
+async fn serve_send_user<S: Backend>(
+    server: &S,
+    id: u64,
+    sender: &str,
+    receiver: &str,
+    msg: String,
+    online: bool,
+) -> Option<ResponseError> {
+    if online {
+        match server.find_user_info(receiver).await {
+            Ok(info) if info.presence == Presence::Offline => return Some(ResponseError::Offline),
+            Ok(_) => {}
+            Err(_) => return Some(ResponseError::NotFound),
+        }
+    }
+
+    match server
+        .deliver(receiver, Response::message(id, sender, msg))
+        .await
+    {
+        Ok(_) => None,
+        Err(QuipError::QueueFull(_)) => Some(ResponseError::QueueFull),
+        Err(_) => Some(ResponseError::NotFound),
+    }
+}
+
+/// Send message to all users in a group except the sender, the sender should
+/// be a member of the group. Members blocking the sender are skipped silently
+/// whatever their policies and pushed to `hidden`, so that the message is
+/// hidden from them in history. Offline members are skipped if `online` is
+/// set. Members with full caches are skipped too, and `QueueFull` is returned
+/// after the message is delivered to the others.
+async fn serve_send_group<S: Backend>(
+    server: &S,
+    id: u64,
+    sender: &str,
+    group: &str,
+    msg: String,
+    online: bool,
+    hidden: &mut Vec<String>,
+) -> Option<ResponseError> {
+    let users = match server.find_group(group).await {
+        Ok(users) => users,
+        Err(_) => return Some(ResponseError::NotFound),
+    };
+
+    if !users.iter().any(|user| user == sender) {
+        return Some(ResponseError::NotFound);
+    }
+
+    let group_sender = format!("{}:{}", group, sender);
+    let mut full = false;
+    for user in users.iter().filter(|user| *user != sender) {
+        if let Ok(Some(_)) = server.find_block(user, sender).await {
+            hidden.push(user.clone());
+            continue;
+        }
+        if online && user_presence(server, user, None).await == Presence::Offline {
+            continue;
+        }
+
+        let resp = Response::message(id, &group_sender, &msg);
+        match server.deliver(user, resp).await {
+            Ok(_) => {}
+            Err(QuipError::QueueFull(_)) => full = true,
+            Err(err) => warn!("Failed to deliver message to {}: {}", user, err),
+        }
+    }
+
+    full.then_some(ResponseError::QueueFull)
+}
+
+/// Serve `Ack` command, the message is removed from unacknowledged messages
+/// of the session.
+async fn serve_ack(conn: &ConnectionRef, id: u64) -> ResponseBody {
+    let unacked = conn.lock().await.unacked.clone();
+    let removed = unacked.lock().await.remove(&id);
+
+    match removed {
+        Some(_) => ResponseBody::Success(Some(id.to_string())),
+        None => ResponseBody::Error(ResponseError::NotFound),
+    }
+}
+
+/// Serve `History` command.
+async fn serve_history<S: Backend>(
+    server: &S,
+    name: &str,
+    target: String,
+    before: Option<u64>,
+    limit: Option<usize>,
+) -> QuipResult<ResponseBody> {
+    let limit = limit
+        .unwrap_or(DEFAULT_HISTORY_LIMIT)
+        .min(MAX_HISTORY_LIMIT);
+
+    match server.find_history(name, &target, before, limit).await {
+        Ok(entries) => {
+            let entries: Vec<String> = entries.iter().map(|entry| entry.to_string()).collect();
+            Ok(ResponseBody::Success(encode_list(&entries)))
+        }
+        Err(err) => Ok(ResponseBody::Error(response_error(err)?)),
+    }
+}
+
+/// Serve `Passwd` command.
+async fn serve_passwd<S: Backend>(
+    server: &S,
+    config: &ServerConfig,
+    name: &str,
+    old: String,
+    new: String,
+) -> QuipResult<ResponseBody> {
+    if config.is_weak_password(&new) {
+        return Ok(ResponseBody::Error(ResponseError::WeakPassword));
+    }
+
+    let res = server.update_password(name, &old, &new).await;
+    response_body(res, name)
+}
+
+/// Serve `GroupCreate` command.
+async fn serve_group_create<S: Backend>(
+    server: &S,
+    owner: &str,
+    group: String,
+) -> QuipResult<ResponseBody> {
+    // Colon is used to separate group and user in `Recv` response.
+    if group.contains(':') {
+        return Ok(ResponseBody::Error(ResponseError::BadCommand));
+    }
+
+    let res = server.create_group(owner, &group).await;
+    response_body(res, group)
+}
+
+/// Serve `Who` command, the user should be a member of the group if provided.
+async fn serve_who<S: Backend>(
+    server: &S,
+    name: &str,
+    group: Option<String>,
+) -> QuipResult<ResponseBody> {
+    let mut users = server.online_users().await?;
+
+    if let Some(group) = group {
+        let members = match server.find_group(&group).await {
+            Ok(members) => members,
+            Err(err) => return Ok(ResponseBody::Error(response_error(err)?)),
+        };
+
+        if !members.iter().any(|user| user == name) {
+            return Ok(ResponseBody::Error(ResponseError::NotFound));
+        }
+
+        users.retain(|user| members.contains(user));
+    }
+
+    Ok(ResponseBody::Success(encode_list(&users)))
+}
+
+/// Serve `Presence` command, users can not be `Offline` while connected.
+async fn serve_presence<S: Backend>(
+    server: &S,
+    conn: &ConnectionRef,
+    presence: Presence,
+) -> ResponseBody {
+    let away = match presence {
+        Presence::Online => false,
+        Presence::Away => true,
+        Presence::Offline => return ResponseBody::Error(ResponseError::BadCommand),
+    };
+
+    let name = conn.lock().await.name.clone();
+    let previous = user_presence(server, &name, None).await;
+
+    conn.lock().await.away = away;
+    update_presence(server, &name, previous).await;
+
+    ResponseBody::Success(Some(presence.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        data::{BackendData, User, UserRole, hash_password},
+        io::tcp::QuipTcpStream,
+        server::{
+            backend::{MemoryBackend, memory::test_backend},
+            config::{OfflineOverflow, OfflinePolicy},
+            service::serve,
+        },
     };
+    use tokio::{
+        io::AsyncWriteExt,
+        net::{
+            TcpListener, TcpStream,
+            tcp::{OwnedReadHalf, OwnedWriteHalf},
+        },
+        task::JoinHandle,
+    };
+
+    async fn session(backend: &MemoryBackend, name: &str) -> ConnectionRef {
+        let session = backend.ensure_conns(name).await.unwrap().remove(0);
+        session.lock().await.status = ConnectionStatus::Auth;
+        session
+    }
+
+    async fn cached(backend: &MemoryBackend, name: &str) -> Vec<String> {
+        let mut lines = vec![];
+        for session in backend.find_conns(name).await.unwrap_or_default() {
+            let queue = session.lock().await.queue.clone();
+            for queued in queue.lock().await.iter() {
+                lines.push(queued.resp.to_string());
+            }
+        }
+        lines
+    }
 
-    let recv_conn = recv_conn.lock().await;
+    #[tokio::test]
+    async fn test_send_group() {
+        let backend = test_backend();
+
+        let body = serve_send(
+            &backend,
+            &session(&backend, "Dessera").await,
+            "G:Team".into(),
+            "Hello".into(),
+            false,
+        )
+        .await
+        .unwrap();
+        assert!(matches!(body, ResponseBody::Success(Some(receiver)) if receiver == "G:Team"));
+        assert_eq!(
+            cached(&backend, "Scarlet").await,
+            vec!["* Recv Team:Dessera Hello 1"]
+        );
+        assert!(cached(&backend, "Dessera").await.is_empty());
 
-    recv_conn
-        .queue
-        .lock()
+        let body = serve_send(
+            &backend,
+            &session(&backend, "Sakuya").await,
+            "G:Team".into(),
+            "Hello".into(),
+            false,
+        )
         .await
-        .push_back(Response::recv(None, sender, msg));
+        .unwrap();
+        assert!(matches!(body, ResponseBody::Error(ResponseError::NotFound)));
+        assert_eq!(cached(&backend, "Scarlet").await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_send_blocked() {
+        let backend = test_backend();
+        let dessera = session(&backend, "Dessera").await;
+        let targets = [("Scarlet", "Dessera"), ("G:Team", "G:Team")];
+
+        for (receiver, _) in targets {
+            serve_send(&backend, &dessera, receiver.into(), "Hi".into(), false)
+                .await
+                .unwrap();
+        }
+        backend
+            .block("Scarlet", "Dessera", BlockPolicy::Silent)
+            .await
+            .unwrap();
+        for conn in backend.find_conns("Scarlet").await.unwrap() {
+            conn.lock().await.queue.lock().await.clear();
+        }
+
+        // Scarlet sees the conversation with Dessera as `Dessera`.
+        for (receiver, target) in targets {
+            let body = serve_send(&backend, &dessera, receiver.into(), "Hello".into(), false)
+                .await
+                .unwrap();
+            assert!(matches!(body, ResponseBody::Success(_)));
+
+            let history = backend.find_history("Dessera", receiver, None, 10);
+            assert_eq!(history.await.unwrap().len(), 2);
+            let history = backend.find_history("Scarlet", target, None, 10);
+            assert_eq!(history.await.unwrap().len(), 1);
+        }
+        assert!(cached(&backend, "Scarlet").await.is_empty());
+
+        // Messages dropped silently are still hidden after unblocking.
+        backend.unblock("Scarlet", "Dessera").await.unwrap();
+        for (_, target) in targets {
+            let history = backend.find_history("Scarlet", target, None, 10);
+            assert_eq!(history.await.unwrap()[0].msg, "Hi");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_group_queue_full() {
+        let backend = test_backend().with_offline_policy(OfflinePolicy {
+            limit: 1,
+            ttl: 0,
+            overflow: OfflineOverflow::Reject,
+        });
+        let dessera = session(&backend, "Dessera").await;
+        session(&backend, "Remilia").await;
+
+        let body = serve_send(&backend, &dessera, "G:Team".into(), "A".into(), false)
+            .await
+            .unwrap();
+        assert!(matches!(body, ResponseBody::Success(_)));
 
-    if recv_conn.status != ConnectionStatus::Cache {
-        recv_conn.notify.notify_one();
+        let body = serve_send(&backend, &dessera, "G:Team".into(), "B".into(), false)
+            .await
+            .unwrap();
+        assert!(matches!(
+            body,
+            ResponseBody::Error(ResponseError::QueueFull)
+        ));
+        assert_eq!(cached(&backend, "Scarlet").await.len(), 1);
+        assert_eq!(cached(&backend, "Remilia").await.len(), 2);
+
+        let history = backend
+            .find_history("Dessera", "G:Team", None, 10)
+            .await
+            .unwrap();
+        assert_eq!(history.len(), 2);
     }
 
-    Ok(ResponseBody::Success(Some(receiver)))
+    #[tokio::test]
+    async fn test_logout_pipelined() {
+        let user = User {
+            name: "Dessera".into(),
+            password: hash_password("Pass").unwrap(),
+            role: UserRole::Member,
+            blocks: Default::default(),
+            banned_until: None,
+            muted_until: None,
+        };
+        let backend = BackendData::new(vec![user], vec![]);
+        let backend = Arc::new(MemoryBackend::from_data(backend).unwrap());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+
+        // Responses to requests pipelined with `Logout` are not written.
+        let (mut reader, mut tx, server) = connect(&listener, &backend).await;
+        tx.write_all(b"C0000 Login Dessera Pass\n").await.unwrap();
+        reader.read_response().await.unwrap();
+        tx.write_all(b"C0001 Whois Nobody\nC0002 Logout\n")
+            .await
+            .unwrap();
+        server.await.unwrap().unwrap();
+
+        // The next session does not receive them.
+        let (mut reader, mut tx, server) = connect(&listener, &backend).await;
+        tx.write_all(b"C0000 Login Dessera Pass\n").await.unwrap();
+        let resp = reader.read_response().await.unwrap();
+        assert_eq!(resp.tag.as_deref(), Some("C0000"));
+        assert!(matches!(resp.body, ResponseBody::Success(_)));
+
+        tx.write_all(b"C0001 Logout\n").await.unwrap();
+        server.await.unwrap().unwrap();
+    }
+
+    async fn connect(
+        listener: &TcpListener,
+        backend: &Arc<MemoryBackend>,
+    ) -> (
+        QuipBufReader<OwnedReadHalf>,
+        OwnedWriteHalf,
+        JoinHandle<QuipResult<()>>,
+    ) {
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (socket, _) = listener.accept().await.unwrap();
+        let backend = backend.clone();
+        let server = tokio::spawn(async move {
+            let io = Box::new(QuipTcpStream::new(socket));
+            serve(&*backend, &ServerConfig::default(), &Shutdown::new(), io).await
+        });
+
+        let (rx, tx) = client.into_split();
+        let mut reader = QuipBufReader::new(rx);
+        reader.read_response().await.unwrap();
+        (reader, tx, server)
+    }
 }
@@ -0,0 +1,159 @@
+//! Keepalive of connections.
+
+use crate::{
+    QuipError, QuipResult,
+    response::{Response, ResponseBody, ResponseError},
+    server::{config::ServerConfig, connection::generate_token},
+};
+use std::time::Duration;
+
+/// Sleep for seconds, or forever if `0`.
+pub async fn sleep_secs(secs: u64) {
+    match secs {
+        0 => std::future::pending().await,
+        secs => tokio::time::sleep(Duration::from_secs(secs)).await,
+    }
+}
+
+/// Keepalive state of a connection.
+///
+/// A `Ping` is sent after the connection is idle for a while, and the
+/// connection should be closed if it is idle again before `Pong` is received.
+pub struct Keepalive {
+    timeout: u64,
+    token: Option<String>,
+}
+
+impl Keepalive {
+    pub fn new(config: &ServerConfig) -> Self {
+        Self {
+            timeout: config.idle_timeout,
+            token: None,
+        }
+    }
+
+    /// Wait until the connection is idle, which never returns if disabled.
+    pub async fn idle(&self) {
+        sleep_secs(self.timeout).await
+    }
+
+    /// Create a `Ping` when the connection is idle, or
+    /// [`QuipError::Disconnect`] if the last one was not answered.
+    pub fn ping(&mut self) -> QuipResult<Response> {
+        if self.token.is_some() {
+            return Err(QuipError::Disconnect);
+        }
+
+        let token = generate_token();
+        self.token = Some(token.clone());
+
+        Ok(Response::ping(token))
+    }
+
+    /// Serve `Pong` command.
+    pub fn pong(&mut self, token: &str) -> ResponseBody {
+        match self.token.as_deref() == Some(token) {
+            true => {
+                self.token = None;
+                ResponseBody::Success(None)
+            }
+            false => ResponseBody::Error(ResponseError::NotFound),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        data::{BackendData, User, UserRole, hash_password},
+        io::{
+            buffer::{QuipBufReader, QuipBufWriter},
+            tcp::QuipTcpStream,
+        },
+        request::{Request, RequestBody},
+        server::{backend::MemoryBackend, service::serve, shutdown::Shutdown},
+    };
+    use tokio::net::{TcpListener, TcpStream};
+
+    #[test]
+    fn test_keepalive_pong() {
+        let mut keepalive = Keepalive::new(&ServerConfig::default());
+
+        let token = match keepalive.ping().unwrap().body {
+            ResponseBody::Ping(token) => token,
+            _ => panic!("Mismatched response, need Ping but others found"),
+        };
+        assert!(matches!(
+            keepalive.pong("Wrong"),
+            ResponseBody::Error(ResponseError::NotFound)
+        ));
+        assert!(matches!(
+            keepalive.pong(&token),
+            ResponseBody::Success(None)
+        ));
+        assert!(keepalive.ping().is_ok());
+        assert!(matches!(keepalive.ping(), Err(QuipError::Disconnect)));
+    }
+
+    #[tokio::test]
+    async fn test_keepalive_timeout() {
+        let config = ServerConfig {
+            login_timeout: 0,
+            idle_timeout: 1,
+            ..Default::default()
+        };
+        let user = User {
+            name: "Dessera".into(),
+            password: hash_password("Pass").unwrap(),
+            role: UserRole::Member,
+            blocks: Default::default(),
+            banned_until: None,
+            muted_until: None,
+        };
+        let backend = MemoryBackend::from_data(BackendData::new(vec![user], vec![])).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (socket, _) = listener.accept().await.unwrap();
+        let server = tokio::spawn(async move {
+            let io = Box::new(QuipTcpStream::new(socket));
+            serve(&backend, &config, &Shutdown::new(), io).await
+        });
+
+        let (rx, tx) = client.into_split();
+        let mut reader = QuipBufReader::new(rx);
+        let mut writer = QuipBufWriter::new(tx);
+
+        reader.read_response().await.unwrap();
+        let login = RequestBody::Login("Dessera".into(), "Pass".into());
+        writer
+            .write_request(Request::new("A", login))
+            .await
+            .unwrap();
+        assert!(matches!(
+            reader.read_response().await.unwrap().body,
+            ResponseBody::Success(_)
+        ));
+
+        assert!(matches!(
+            reader.read_response().await.unwrap().body,
+            ResponseBody::Ping(_)
+        ));
+        let pong = RequestBody::Pong("Wrong".into());
+        writer.write_request(Request::new("B", pong)).await.unwrap();
+        assert!(matches!(
+            reader.read_response().await.unwrap().body,
+            ResponseBody::Error(ResponseError::NotFound)
+        ));
+
+        // The connection is closed after another idle period without `Pong`.
+        assert!(matches!(
+            reader.read_response().await,
+            Err(QuipError::Disconnect)
+        ));
+        server.await.unwrap().unwrap();
+    }
+}
@@ -1,4 +1,7 @@
+mod access;
 mod auth;
+mod keepalive;
+mod moderation;
 mod unauth;
 
 use crate::{
@@ -7,18 +10,47 @@ use crate::{
         DynamicQuipIO, QuipInput, QuipOutput,
         buffer::{QuipBufReader, QuipBufWriter},
     },
-    server::backend::Backend,
+    response::{
+        Capability, NoticeLevel, Presence, Response, ResponseBody, ResponseError, encode_list,
+    },
+    server::{
+        backend::Backend,
+        config::ServerConfig,
+        connection::{ConnectionRef, ConnectionStatus, presence_of},
+        shutdown::Shutdown,
+    },
 };
-use log::info;
+use log::{info, warn};
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+/// Notice sent to connections when server is shutting down.
+fn shutdown_notice() -> Response {
+    Response::notice(NoticeLevel::Warn, "Server is shutting down")
+}
+
+/// Notice sent to connections which did not authenticate in time.
+fn login_timeout_notice() -> Response {
+    Response::notice(NoticeLevel::Warn, "Login timed out")
+}
 
 /// General serve entry, which represents the entire lifetime of a connection.
-pub async fn serve<S: Backend>(server: &S, conn: DynamicQuipIO) -> QuipResult<()> {
-    let (rx, tx) = {
+pub async fn serve<S: Backend>(
+    server: &S,
+    config: &ServerConfig,
+    shutdown: &Shutdown,
+    conn: DynamicQuipIO,
+) -> QuipResult<()> {
+    let (rx, mut tx) = {
         let conns = conn.duplex();
-        (QuipBufReader::new(conns.0), QuipBufWriter::new(conns.1))
+        let reader = QuipBufReader::new(conns.0).with_max_line(config.max_line_len);
+        (reader, QuipBufWriter::new(conns.1))
     };
 
-    match serve_inner(server, rx, tx).await {
+    // Greeting is written before any request is read.
+    tx.write_response(Response::hello(config.max_line_len))
+        .await?;
+
+    match serve_inner(server, config, shutdown, rx, tx).await {
         Ok(_) | Err(QuipError::Disconnect) => Ok(()),
         Err(err) => Err(err),
     }
@@ -26,29 +58,143 @@ pub async fn serve<S: Backend>(server: &S, conn: DynamicQuipIO) -> QuipResult<()
 
 async fn serve_inner<S: Backend, R: QuipInput, W: QuipOutput>(
     server: &S,
+    config: &ServerConfig,
+    shutdown: &Shutdown,
     mut rx: QuipBufReader<R>,
     mut tx: QuipBufWriter<W>,
 ) -> QuipResult<()> {
-    let conn = unauth::serve(server, &mut rx, &mut tx).await?;
-    let conn_name = {
+    let conn = tokio::select! {
+        conn = unauth::serve(server, config, &mut rx, &mut tx) => Ok(conn?),
+        _ = keepalive::sleep_secs(config.login_timeout) => Err(login_timeout_notice()),
+        _ = shutdown.wait() => Err(shutdown_notice()),
+    };
+
+    let conn = match conn {
+        Ok(conn) => conn,
+        Err(notice) => return tx.write_response(notice).await,
+    };
+    let (conn_name, conn_id) = {
         let conn = conn.lock().await;
-        conn.name.clone()
+        (conn.name.clone(), conn.id)
+    };
+
+    info!("User {} login, session {}", conn_name, conn_id);
+    let presence = user_presence(server, &conn_name, Some(&conn)).await;
+    update_presence(server, &conn_name, presence).await;
+
+    // Write task returns when shutting down, and read task returns when
+    // client leaves, either of them closes the connection.
+    let res = tokio::select! {
+        res = auth::serve_read(server, config, conn.clone(), &mut rx) => res,
+        res = auth::serve_write(server, conn.clone(), &mut tx, shutdown) => res,
+    };
+
+    // Sessions are kept for `Resume` unless the user logged out.
+    let token = conn.lock().await.token.clone();
+    let token = token.filter(|_| config.resume_grace > 0 && !shutdown.is_triggered());
+
+    let presence = user_presence(server, &conn_name, None).await;
+    match &token {
+        Some(_) => server.detach_conn(&conn_name, conn_id).await?,
+        None => server.unload_conn(&conn_name, conn_id).await?,
+    }
+
+    info!("User {} logout, session {}", conn_name, conn_id);
+    update_presence(server, &conn_name, presence).await;
+
+    if let Some(token) = token {
+        // Socket is closed while waiting, e.g. after keepalive timed out.
+        drop((rx, tx));
+
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(config.resume_grace)) => {}
+            _ = shutdown.wait() => {}
+        }
+
+        server.expire_conn(&conn_name, &token).await?;
+    }
+
+    res
+}
+
+/// Presence of a user, sessions except the given one are considered.
+async fn user_presence<S: Backend>(
+    server: &S,
+    name: &str,
+    except: Option<&ConnectionRef>,
+) -> Presence {
+    let mut conns = server.find_conns(name).await.unwrap_or_default();
+    if let Some(except) = except {
+        conns.retain(|conn| !Arc::ptr_eq(conn, except));
+    }
+
+    presence_of(&conns).await
+}
+
+/// Broadcast presence of a user if it differs from the previous one.
+async fn update_presence<S: Backend>(server: &S, name: &str, previous: Presence) {
+    let presence = user_presence(server, name, None).await;
+    if presence != previous {
+        broadcast_presence(server, name, presence).await;
+    }
+}
+
+/// Push presence of a user to its audience, offline users are skipped.
+async fn broadcast_presence<S: Backend>(server: &S, name: &str, presence: Presence) {
+    let audience = match server.presence_audience(name).await {
+        Ok(audience) => audience,
+        Err(err) => {
+            warn!("Failed to find presence audience of {}: {}", name, err);
+            return;
+        }
     };
 
-    info!("User {} login", conn_name);
+    for user in audience {
+        let conns = match server.find_conns(&user).await {
+            Ok(conns) => conns,
+            Err(_) => continue,
+        };
 
-    // TODO: Use flag rather than `try_join`.
-    let res = tokio::try_join!(
-        auth::serve_read(server, conn.clone(), &mut rx),
-        auth::serve_write(server, conn.clone(), &mut tx)
-    );
+        for conn in conns {
+            let conn = conn.lock().await;
+            if conn.status == ConnectionStatus::Auth {
+                conn.push(Response::presence(name, presence)).await;
+            }
+        }
+    }
+}
 
-    server.unload_conn(&conn_name).await?;
+/// Enable requested capabilities, the response payload is the enabled ones.
+fn negotiate(requested: Vec<Capability>) -> (HashSet<Capability>, ResponseBody) {
+    let mut caps: HashSet<Capability> = requested.into_iter().collect();
+    if caps.contains(&Capability::Ack) {
+        caps.insert(Capability::Ids);
+    }
 
-    info!("User {} logout", conn_name);
+    let mut enabled: Vec<Capability> = caps.iter().copied().collect();
+    enabled.sort();
+    let enabled: Vec<String> = enabled.iter().map(|cap| cap.to_string()).collect();
 
+    (caps, ResponseBody::Success(encode_list(&enabled)))
+}
+
+/// Convert errors from backend to [`ResponseError`], unexpected errors are
+/// returned directly.
+fn response_error(err: QuipError) -> QuipResult<ResponseError> {
+    match err {
+        QuipError::Duplicate(_) => Ok(ResponseError::Duplicate),
+        QuipError::NotFound(_) => Ok(ResponseError::NotFound),
+        QuipError::Authorize(_) => Ok(ResponseError::Unauthorized),
+        QuipError::Forbidden(_) => Ok(ResponseError::Forbidden),
+        err => Err(err),
+    }
+}
+
+/// Convert result from backend to [`ResponseBody`], with a message for
+/// success.
+fn response_body(res: QuipResult<()>, msg: impl Into<String>) -> QuipResult<ResponseBody> {
     match res {
-        Ok(_) => Ok(()),
-        Err(err) => Err(err),
+        Ok(_) => Ok(ResponseBody::Success(Some(msg.into()))),
+        Err(err) => Ok(ResponseBody::Error(response_error(err)?)),
     }
 }
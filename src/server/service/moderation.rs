@@ -0,0 +1,135 @@
+//! Moderation and announcement commands of admins.
+
+use crate::{
+    QuipError, QuipResult,
+    data::FOREVER,
+    response::{NoticeLevel, Response, ResponseBody},
+    server::{
+        backend::Backend,
+        connection::{ConnectionStatus, unix_time},
+        service::{access::check_admin, response_body},
+    },
+};
+
+/// Expiry of a ban or mute lasting for seconds, or forever without a
+/// duration.
+fn expiry(duration: Option<u64>) -> u64 {
+    match duration {
+        Some(duration) => unix_time().saturating_add(duration),
+        None => FOREVER,
+    }
+}
+
+/// Describe a moderation action for notices.
+fn describe(action: &str, operator: &str, duration: Option<u64>) -> String {
+    match duration {
+        Some(duration) => format!("{} by {} for {} seconds", action, operator, duration),
+        None => format!("{} by {}", action, operator),
+    }
+}
+
+/// Push a notice to all online sessions of a user.
+async fn notify_user<S: Backend>(server: &S, name: &str, notice: &Response) {
+    for conn in server.find_conns(name).await.unwrap_or_default() {
+        let conn = conn.lock().await;
+        if conn.status == ConnectionStatus::Auth {
+            conn.push(notice.clone()).await;
+        }
+    }
+}
+
+/// Serve `Kick` command.
+pub async fn serve_kick<S: Backend>(
+    server: &S,
+    operator: &str,
+    name: String,
+    reason: Option<String>,
+) -> QuipResult<ResponseBody> {
+    let msg = match reason {
+        Some(reason) => format!("{}: {}", describe("Kicked", operator, None), reason),
+        None => describe("Kicked", operator, None),
+    };
+
+    let res = async {
+        check_admin(server, operator).await?;
+
+        let notice = Response::notice(NoticeLevel::Warn, msg);
+        match server.close_conns(&name, notice).await? {
+            0 => Err(QuipError::NotFound(format!("No session of {}", name))),
+            _ => Ok(()),
+        }
+    };
+    response_body(res.await, name)
+}
+
+/// Serve `Ban` command, sessions of the user are closed.
+pub async fn serve_ban<S: Backend>(
+    server: &S,
+    operator: &str,
+    name: String,
+    duration: Option<u64>,
+) -> QuipResult<ResponseBody> {
+    let res = async {
+        check_admin(server, operator).await?;
+        server.ban_user(&name, Some(expiry(duration))).await?;
+
+        let notice = Response::notice(NoticeLevel::Warn, describe("Banned", operator, duration));
+        server.close_conns(&name, notice).await.map(|_| ())
+    };
+    response_body(res.await, name)
+}
+
+/// Serve `Mute` command, sessions of the user are notified.
+pub async fn serve_mute<S: Backend>(
+    server: &S,
+    operator: &str,
+    name: String,
+    duration: Option<u64>,
+) -> QuipResult<ResponseBody> {
+    let res = async {
+        check_admin(server, operator).await?;
+        server.mute_user(&name, Some(expiry(duration))).await?;
+
+        let notice = Response::notice(NoticeLevel::Warn, describe("Muted", operator, duration));
+        notify_user(server, &name, &notice).await;
+        Ok(())
+    };
+    response_body(res.await, name)
+}
+
+/// Serve `Unban` and `Unmute` commands.
+pub async fn serve_lift<S: Backend>(
+    server: &S,
+    operator: &str,
+    name: String,
+    ban: bool,
+) -> QuipResult<ResponseBody> {
+    let res = async {
+        check_admin(server, operator).await?;
+        match ban {
+            true => server.ban_user(&name, None).await,
+            false => server.mute_user(&name, None).await,
+        }
+    };
+    response_body(res.await, name)
+}
+
+/// Serve `Announce` command, the notice is pushed to all online sessions
+/// including the sender's.
+pub async fn serve_announce<S: Backend>(
+    server: &S,
+    operator: &str,
+    msg: String,
+) -> QuipResult<ResponseBody> {
+    let notice = Response::notice(NoticeLevel::Info, format!("[{}] {}", operator, msg));
+
+    let res = async {
+        check_admin(server, operator).await?;
+
+        for name in server.online_users().await? {
+            notify_user(server, &name, &notice).await;
+        }
+        Ok(())
+    };
+    response_body(res.await, msg)
+}
@@ -7,56 +7,93 @@ use crate::{
         buffer::{QuipBufReader, QuipBufWriter},
     },
     request::RequestBody,
-    response::{Response, ResponseBody, ResponseError},
-    server::{backend::Backend, connection::ConnectionRef},
+    response::{Capability, Response, ResponseBody, ResponseError, encode_list},
+    server::{
+        backend::Backend,
+        config::ServerConfig,
+        connection::{ConnectionRef, generate_token},
+        limit::SessionLimiter,
+        service::{negotiate, response_body, response_error},
+    },
 };
+use std::collections::HashSet;
 
-/// Serve entry for unauthenticated connection, which waits for `Login` command
-/// and go to next step.
+/// Serve entry for unauthenticated connection, which waits for `Login` or
+/// `Resume` command and go to next step.
+///
+/// Capabilities enabled before authenticated are applied to the session.
 pub async fn serve<S: Backend, R: QuipInput, W: QuipOutput>(
     server: &S,
+    config: &ServerConfig,
     reader: &mut QuipBufReader<R>,
     writer: &mut QuipBufWriter<W>,
 ) -> QuipResult<ConnectionRef> {
-    let (name, resp) = serve_inner(server, reader, writer).await?;
-    let conn = server.find_conn(&name).await?;
+    let (conn, resp, caps) = serve_inner(server, config, reader, writer).await?;
 
-    {
-        let conn = conn.lock().await;
-        conn.queue.lock().await.push_back(resp);
-        conn.notify.notify_one();
-    }
+    let mut conn_handle = conn.lock().await;
+    conn_handle.caps = caps;
+    conn_handle.push(resp).await;
+    drop(conn_handle);
 
     Ok(conn)
 }
 
 async fn serve_inner<S: Backend, R: QuipInput, W: QuipOutput>(
     server: &S,
+    config: &ServerConfig,
     reader: &mut QuipBufReader<R>,
     writer: &mut QuipBufWriter<W>,
-) -> QuipResult<(String, Response)> {
+) -> QuipResult<(ConnectionRef, Response, HashSet<Capability>)> {
+    let mut caps = HashSet::new();
+    let mut limiter = SessionLimiter::new(config);
+
+    // Unauthenticated connections are not pinged, they are closed after the
+    // login timeout instead.
     Ok(loop {
         let resp = match reader.read_request().await {
             Ok(request) => {
+                let allowed = limiter
+                    .check(&request.body)
+                    .inspect_err(|_| warn!("Unknown: too many rate limited requests"))?;
+
                 let body = match request.body {
+                    _ if !allowed => ResponseBody::Error(ResponseError::RateLimited),
                     RequestBody::Login(name, password) => {
-                        let body = serve_login(server, &name, &password).await?;
-
-                        match body {
-                            ResponseBody::Success(_) => {
-                                break (name, Response::new(Some(request.tag), body));
+                        match serve_login(server, &name, &password, &caps).await? {
+                            (body, Some(conn)) => {
+                                break (conn, Response::new(Some(request.tag), body), caps);
+                            }
+                            (body, None) => body,
+                        }
+                    }
+                    RequestBody::Resume(token) => {
+                        match serve_resume(server, &token, &caps).await? {
+                            (body, Some(conn)) => {
+                                break (conn, Response::new(Some(request.tag), body), caps);
                             }
-                            _ => body,
+                            (body, None) => body,
                         }
                     }
+                    RequestBody::Register(name, password) => {
+                        serve_register(server, config, name, password).await?
+                    }
                     RequestBody::Logout => return Err(QuipError::Disconnect),
                     RequestBody::Nop => ResponseBody::Success(None),
+                    RequestBody::Cap(requested) => {
+                        let (enabled, body) = negotiate(requested);
+                        caps = enabled;
+                        body
+                    }
                     _ => ResponseBody::Error(ResponseError::Unauthorized),
                 };
 
                 debug!("Unknown: {}", request.tag);
                 Response::new(Some(request.tag), body)
             }
+            Err(err @ QuipError::TooLong(_)) => {
+                warn!("Unknown: {}", err);
+                Response::error(None, ResponseError::TooLong)
+            }
             Err(QuipError::Parse(msg)) => {
                 warn!("Unknown: {}", msg);
                 Response::error(None, ResponseError::BadCommand)
@@ -68,19 +105,67 @@ async fn serve_inner<S: Backend, R: QuipInput, W: QuipOutput>(
     })
 }
 
-/// Serve `Login` command.
+/// Serve `Login` command, the new session is returned if succeeded.
 async fn serve_login<S: Backend>(
     server: &S,
     name: &str,
     password: &str,
+    caps: &HashSet<Capability>,
+) -> QuipResult<(ResponseBody, Option<ConnectionRef>)> {
+    match server.load_conn(name, password).await {
+        Ok(conn) => Ok((issue_token(&conn, caps).await, Some(conn))),
+        Err(err) => Ok((ResponseBody::Error(response_error(err)?), None)),
+    }
+}
+
+/// Serve `Resume` command, the detached session is returned if succeeded.
+async fn serve_resume<S: Backend>(
+    server: &S,
+    token: &str,
+    caps: &HashSet<Capability>,
+) -> QuipResult<(ResponseBody, Option<ConnectionRef>)> {
+    match server.resume_conn(token).await {
+        Ok(conn) => Ok((issue_token(&conn, caps).await, Some(conn))),
+        Err(err) => Ok((ResponseBody::Error(response_error(err)?), None)),
+    }
+}
+
+/// Issue a new token to resume the session if the client has `resume`, the
+/// response payload is the user name and the token. Legacy clients get only
+/// the user name, and their sessions can not be resumed.
+async fn issue_token(conn: &ConnectionRef, caps: &HashSet<Capability>) -> ResponseBody {
+    let mut conn = conn.lock().await;
+    if !caps.contains(&Capability::Resume) {
+        conn.token = None;
+        return ResponseBody::Success(Some(conn.name.clone()));
+    }
+
+    let token = generate_token();
+    conn.token = Some(token.clone());
+
+    ResponseBody::Success(encode_list(&[conn.name.as_str(), token.as_str()]))
+}
+
+/// Serve `Register` command.
+async fn serve_register<S: Backend>(
+    server: &S,
+    config: &ServerConfig,
+    name: String,
+    password: String,
 ) -> QuipResult<ResponseBody> {
-    let resp = match server.load_conn(name, password).await {
-        Ok(_) => ResponseBody::Success(Some(name.to_string())),
-        Err(QuipError::Duplicate(_)) => ResponseBody::Error(ResponseError::Duplicate),
-        Err(QuipError::NotFound(_)) => ResponseBody::Error(ResponseError::NotFound),
-        Err(QuipError::Authorize(_)) => ResponseBody::Error(ResponseError::Unauthorized),
-        Err(err) => return Err(err),
-    };
-
-    Ok(resp)
+    if !config.allow_register {
+        return Ok(ResponseBody::Error(ResponseError::RegisterDisabled));
+    }
+
+    // Colon is used to separate group and user in `Recv` response.
+    if name.contains(':') {
+        return Ok(ResponseBody::Error(ResponseError::BadCommand));
+    }
+
+    if config.is_weak_password(&password) {
+        return Ok(ResponseBody::Error(ResponseError::WeakPassword));
+    }
+
+    let res = server.create_user(&name, &password).await;
+    response_body(res, name)
 }
@@ -0,0 +1,43 @@
+//! Shutdown signal of server.
+
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// Shutdown signal shared by server and connections.
+///
+/// Clones of [`Shutdown`] share the same signal, so it can be triggered from
+/// anywhere, such as a signal handler.
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    tx: Arc<watch::Sender<bool>>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self {
+            tx: Arc::new(watch::Sender::new(false)),
+        }
+    }
+
+    /// Trigger the shutdown.
+    pub fn trigger(&self) {
+        self.tx.send_replace(true);
+    }
+
+    /// Check if the shutdown was triggered.
+    pub fn is_triggered(&self) -> bool {
+        *self.tx.borrow()
+    }
+
+    /// Wait until the shutdown is triggered.
+    pub async fn wait(&self) {
+        let mut rx = self.tx.subscribe();
+        let _ = rx.wait_for(|triggered| *triggered).await;
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
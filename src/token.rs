@@ -22,8 +22,7 @@ pub fn tokenize(input: impl AsRef<str>) -> QuipResult<Vec<String>> {
     let mut res = Vec::new();
     let mut curr = String::new();
 
-    let mut chars = input.trim().chars();
-    while let Some(ch) = chars.next() {
+    for ch in input.trim().chars() {
         match ch {
             '\\' if !in_escape => {
                 in_escape = true;
@@ -66,17 +65,16 @@ pub fn detokenize(input: &Vec<impl AsRef<str>>) -> String {
 
         if item.contains(' ') {
             curr.push('\"');
-            curr += &escape_token(&item);
+            curr += &escape_token(item);
             curr.push('\"');
         } else {
-            curr = escape_token(&item);
+            curr = escape_token(item);
         }
 
         res.push(curr);
     }
 
-    let res = res.join(" ");
-    res
+    res.join(" ")
 }
 
 fn escape_token(input: &str) -> String {